@@ -1,127 +1,491 @@
 use std::{
-    borrow::Cow, collections::VecDeque, ffi::CStr, fmt::Display, future::Future, sync::Arc,
-    time::Duration,
+    cmp::Reverse,
+    collections::VecDeque,
+    env,
+    fs::{self, File},
+    future::Future,
+    io::{self, Write},
+    os::unix::{ffi::OsStrExt, fs::OpenOptionsExt},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 use color_eyre::{eyre::Context, Result};
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use futures_util::future::join_all;
+use log::{debug, error, warn};
 use ratatui::{
     buffer::Buffer,
-    layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{Modifier, Style, Stylize},
+    layout::{Alignment, Constraint, Direction, Flex, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
     symbols::border,
     text::{Line, Text},
     widgets::{
         Block, Borders, Cell, Clear, Paragraph, Row, StatefulWidget, Table, TableState, Widget,
+        Wrap,
     },
     Frame,
 };
+use rustix::fs::OFlags;
 use secstr::SecStr;
-use tokio::{runtime::Runtime, task::JoinHandle};
+use tokio::{
+    runtime::Runtime,
+    signal::unix::{signal, SignalKind},
+    task::{AbortHandle, JoinHandle, JoinSet},
+};
+use zvariant::OwnedObjectPath;
 
 use crate::{
-    device::{Device, DeviceState},
-    tui,
-    udisks2::{BlockDevice, BlockDeviceKind, BlockProxy, Client, EncryptedProxy, FilesystemProxy},
+    bind_mount::{self, BindMount},
+    clipboard,
+    config::{Column, Config, EnterAction, SortMode},
+    device::{
+        self, eject_drive, flush, list_devices, pending_writes, removable_drives, Device,
+        DeviceMessage, DeviceState, Disk, DriveInfo, EraseMethod, GuiDevice, PartitionTableType,
+        SizeMode, Usage,
+    },
+    export::{self, Format},
+    history::{format_ago, History},
+    keymap::{self, Action, Key, Keymap},
+    label, luks,
+    mount_options::MountOptions,
+    processes, tui,
+    udisks2::{dbus_error_name, BlockDeviceKind, Client, UdisksError},
 };
 
+/// Partitions are aligned to this, so sizes are rounded down to it.
+const MIB: u64 = 1 << 20;
+
+/// How long listing the devices waits for each of them, see
+/// [`App::get_or_refresh_devices`].
+const DEVICE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Width of the details pane next to the table.
+const DETAILS_PANE_WIDTH: u16 = 50;
+/// Rows the table keeps at least when the details pane is below it.
+const MIN_TABLE_HEIGHT: u16 = 8;
+
 pub struct App {
+    config: Config,
     client: Client,
     devices: Arc<[Device]>,
     gui_devices: Box<[GuiDevice]>,
     selected_device_index: usize,
     passphrase: Option<String>,
-    reading_passphrase: bool,
+    state: AppState,
     state_msg: Option<String>,
     exit: bool,
+    /// An exit requested together with a mount was put on hold to ask for the
+    /// passphrase, and should resume once it is entered.
     exit_after_passphrase: bool,
+    /// Mount point of the last mounted device.
     exit_mount_point: Option<String>,
+    /// Print `exit_mount_point` after exiting, independently of whether the
+    /// exit was triggered by the mount itself or by a later quit.
     print_on_exit: bool,
+    /// Open the mount point in the file manager once the pending mount
+    /// finishes.
+    open_after_mount: bool,
+    /// Tasks aborted by quitting without waiting for them.
+    abandoned: usize,
     runtime: Runtime,
-    tasks: VecDeque<JoinHandle<Result<Message>>>,
+    tasks: VecDeque<Task>,
+    bind_mounts: Vec<BindMount>,
+    last_error: Option<ErrorDetails>,
+    size_mode: SizeMode,
+    long_names: bool,
+    /// Show the size of the drive next to that of partitions.
+    drive_size: bool,
+    sort: SortMode,
+    /// Indices of the devices in the order they are shown, see
+    /// [`App::sort_devices`]. The devices themselves stay in place since
+    /// running tasks refer to them by index.
+    order: Vec<usize>,
+    /// Hide the devices that can't be mounted right away, i.e. locked ones
+    /// and empty drives.
+    filesystems_only: bool,
+    /// Show the devices hidden with x, see [`History::is_hidden`].
+    show_hidden: bool,
+    /// Show the details of the selected device next to the list.
+    details_pane: bool,
+    /// Count typed before a motion, e.g. the 5 of `5j`.
+    count: Option<usize>,
+    /// The jump key was pressed, so the next one picks the state to jump to.
+    jumping: bool,
+    history: History,
+    /// Popups waiting for the current one to be closed, see
+    /// [`App::show_popup`].
+    pending_states: VecDeque<AppState>,
+    /// Where status messages are written to, see [`Config::announce`].
+    announce: Option<File>,
+    /// Last status message written to `announce`.
+    announced: Option<String>,
+    scan: Option<Scan>,
+    /// Device unmounted last, which Ctrl-Z mounts again, asking for the
+    /// passphrase if it was locked too.
+    last_unmounted: Option<usize>,
+    /// When the last key was pressed, for [`Config::idle_timeout_secs`].
+    last_input: Instant,
+    /// When m was last pressed and on which device, for
+    /// [`Config::double_tap_ms`].
+    last_mount: Option<(Instant, usize)>,
+    /// Set by SIGTERM, SIGINT or SIGHUP, see [`App::watch_signals`].
+    terminated: Arc<AtomicBool>,
+    keymap: Keymap,
+}
+
+#[derive(Debug)]
+enum AppState {
+    DisksList,
+    ReadingPassphrase {
+        idx: usize,
+        /// Options to mount with once unlocked, `None` to only unlock.
+        options: Option<MountOptions>,
+        /// What the volume is enrolled to be unlocked with instead, e.g. a
+        /// security key, which udisks2 can't use.
+        needs: Option<&'static str>,
+    },
+    ChoosingMountOptions {
+        selected: usize,
+    },
+    ChoosingSubvolume {
+        idx: usize,
+        subvolumes: Vec<String>,
+        selected: usize,
+    },
+    ChoosingEraseMethod {
+        idx: usize,
+        methods: Vec<EraseMethod>,
+        selected: usize,
+    },
+    ConfirmingWipe {
+        idx: usize,
+        method: EraseMethod,
+        input: String,
+    },
+    ChoosingPartitionTable {
+        idx: usize,
+        disk: Disk,
+        selected: usize,
+    },
+    /// The name of the disk has to be typed to create the partition table.
+    ConfirmingPartitionTable {
+        idx: usize,
+        disk: Disk,
+        table: PartitionTableType,
+        input: String,
+    },
+    Input {
+        action: InputAction,
+        input: String,
+        error: Option<String>,
+    },
+    Details {
+        idx: usize,
+    },
+    Confirming {
+        action: ConfirmAction,
+        message: String,
+    },
+    Error {
+        scroll: u16,
+    },
+    /// Every action and its keys.
+    Help {
+        scroll: u16,
+    },
+}
+
+/// A task spawned with [`App::spawn`] or [`App::spawn_for`].
+struct Task {
+    handle: JoinHandle<Result<DeviceMessage>>,
+    /// The device the task is about, to find its index again once it
+    /// finishes since the list may have been refreshed meanwhile. `None` for
+    /// tasks about every device.
+    path: Option<OwnedObjectPath>,
+}
+
+impl AppState {
+    /// The index of the device the popup is about, `None` if it's about none
+    /// in particular.
+    fn idx_mut(&mut self) -> Option<&mut usize> {
+        match self {
+            AppState::ReadingPassphrase { idx, .. }
+            | AppState::ChoosingSubvolume { idx, .. }
+            | AppState::ChoosingEraseMethod { idx, .. }
+            | AppState::ConfirmingWipe { idx, .. }
+            | AppState::ChoosingPartitionTable { idx, .. }
+            | AppState::ConfirmingPartitionTable { idx, .. }
+            | AppState::Details { idx } => Some(idx),
+            AppState::Input { action, .. } => action.idx_mut(),
+            AppState::Confirming { action, .. } => action.idx_mut(),
+            AppState::DisksList
+            | AppState::ChoosingMountOptions { .. }
+            | AppState::Error { .. }
+            | AppState::Help { .. } => None,
+        }
+    }
+}
+
+/// A running [`App::get_or_refresh_devices`], for showing a spinner and its
+/// progress until it finishes.
+struct Scan {
+    task: AbortHandle,
+    started: Instant,
+    /// Devices queried so far.
+    done: Arc<AtomicUsize>,
+    /// Devices to query, zero until they are listed.
+    total: Arc<AtomicUsize>,
 }
 
 #[derive(Debug)]
-pub struct GuiDevice {
-    info: GuiDeviceInfo,
-    state: DeviceState,
+struct ErrorDetails {
+    message: String,
+    dbus_name: Option<String>,
+    kind: Option<UdisksError>,
+}
+
+#[derive(Debug)]
+enum ConfirmAction {
+    Unmount(usize),
+    /// Resize the device to the given size in bytes.
+    Resize(usize, u64),
+    Check {
+        idx: usize,
+        repair: bool,
+    },
+    /// Chown the filesystem to the user, every file on it if `recursive`.
+    TakeOwnership {
+        idx: usize,
+        recursive: bool,
+    },
+    MountAndExit,
+}
+
+impl ConfirmAction {
+    /// The index of the device to confirm the action on, `None` if it acts
+    /// on the selected one.
+    fn idx_mut(&mut self) -> Option<&mut usize> {
+        match self {
+            ConfirmAction::Unmount(idx)
+            | ConfirmAction::Resize(idx, _)
+            | ConfirmAction::Check { idx, .. }
+            | ConfirmAction::TakeOwnership { idx, .. } => Some(idx),
+            ConfirmAction::MountAndExit => None,
+        }
+    }
 }
 
 #[derive(Debug)]
-pub struct GuiDeviceInfo {
-    pub name: String,
-    pub label: String,
-    pub size: String,
-    pub mount_point: String,
+enum InputAction {
+    BindMount(usize),
+    MountAt(usize),
+    Resize(usize),
+    MountAs(usize),
+    SetLabel(usize),
+    Export,
 }
 
-pub enum Message {
-    Mounted(usize, String),
-    Unmounted(usize),
-    Locked(usize),
-    UnmountedAndLocked(usize, GuiDeviceInfo),
-    UnlockedAndMounted(usize, String, GuiDeviceInfo),
-    AlreadyMounted(usize, String),
-    AlreadyUnmounted(usize),
-    AlreadyLocked(usize),
-    Devices(Vec<GuiDevice>, Vec<Device>),
-    PassphraseRequired(usize),
-    Ejected(usize),
+impl InputAction {
+    /// The index of the device the input is for, `None` if it's for none.
+    fn idx_mut(&mut self) -> Option<&mut usize> {
+        match self {
+            InputAction::BindMount(idx)
+            | InputAction::MountAt(idx)
+            | InputAction::Resize(idx)
+            | InputAction::MountAs(idx)
+            | InputAction::SetLabel(idx) => Some(idx),
+            InputAction::Export => None,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            InputAction::BindMount(_) => " Bind mount at directory ",
+            InputAction::MountAt(_) => " Mount at empty directory ",
+            InputAction::Resize(_) => " Resize to (e.g. 32G, 500 MB, 150%) ",
+            InputAction::MountAs(_) => " Mount as filesystem type (e.g. ntfs3) ",
+            InputAction::SetLabel(_) => " Set label ",
+            InputAction::Export => " Export to .csv or .tsv file, empty to copy ",
+        }
+    }
 }
 
 impl App {
-    pub fn new() -> Result<Self> {
+    pub fn new(config: Config) -> Result<Self> {
         let runtime = Runtime::new()?;
-        let client = runtime.block_on(Client::new())?;
+        let client = runtime.block_on(Client::connect_to(config.session_bus))?;
+        let announce = config
+            .announce
+            .as_ref()
+            .map(|path| {
+                // nonblocking, so opening a named pipe fails instead of hanging
+                // when nothing reads it
+                File::options()
+                    .append(true)
+                    .create(true)
+                    .custom_flags(OFlags::NONBLOCK.bits() as i32)
+                    .open(path)
+                    .wrap_err_with(|| format!("failed to open {}", path.display()))
+            })
+            .transpose()?;
+        let keymap = Keymap::new(&config.keys);
         let mut app = Self {
+            sort: config.sort,
+            long_names: config.long_names,
+            drive_size: config.drive_size,
+            config,
             client,
             gui_devices: Box::new([]),
             devices: Arc::new([]),
             selected_device_index: 0,
             passphrase: None,
-            reading_passphrase: false,
+            state: AppState::DisksList,
             state_msg: None,
             exit: false,
             exit_after_passphrase: false,
             exit_mount_point: None,
             print_on_exit: false,
+            open_after_mount: false,
+            abandoned: 0,
             runtime,
             tasks: VecDeque::new(),
+            bind_mounts: Vec::new(),
+            last_error: None,
+            size_mode: SizeMode::default(),
+            order: Vec::new(),
+            filesystems_only: false,
+            show_hidden: false,
+            details_pane: false,
+            count: None,
+            jumping: false,
+            announce,
+            announced: None,
+            last_input: Instant::now(),
+            last_mount: None,
+            terminated: Arc::new(AtomicBool::new(false)),
+            keymap,
+            last_unmounted: None,
+            scan: None,
+            pending_states: VecDeque::new(),
+            history: History::load().unwrap_or_else(|err| {
+                warn!("failed to load the mount history: {err:#}");
+                History::default()
+            }),
         };
+        if let Some(path) = &app.config.created {
+            app.state_msg = Some(format!("Created the default config at {}", path.display()));
+        }
+        app.watch_signals()?;
         app.get_or_refresh_devices();
         Ok(app)
     }
 
+    /// Makes the run loop return when the process is asked to terminate,
+    /// e.g. when the terminal window is closed, so the terminal gets restored
+    /// instead of being left in raw mode and the alternate screen. Ctrl-C is
+    /// a key press while the TUI runs, so SIGINT only comes from `kill`.
+    fn watch_signals(&mut self) -> Result<()> {
+        let _guard = self.runtime.enter();
+        let handler = |kind| signal(kind).wrap_err("failed to install a signal handler");
+        let mut term = handler(SignalKind::terminate())?;
+        let mut int = handler(SignalKind::interrupt())?;
+        let mut hup = handler(SignalKind::hangup())?;
+        let terminated = Arc::clone(&self.terminated);
+        self.runtime.spawn(async move {
+            tokio::select! {
+                _ = term.recv() => {}
+                _ = int.recv() => {}
+                _ = hup.recv() => {}
+            }
+            terminated.store(true, Ordering::SeqCst);
+        });
+        Ok(())
+    }
+
     pub fn run(&mut self, terminal: &mut tui::Tui) -> Result<()> {
-        while !self.exit {
+        loop {
             terminal.draw(|frame| self.render_frame(frame))?;
             self.check_finished_tasks()?;
+            if let AppState::DisksList = self.state {
+                if let Some(state) = self.pending_states.pop_front() {
+                    self.state = state;
+                }
+            }
+            self.announce_status();
+            self.check_idle();
+            if self.terminated.load(Ordering::SeqCst) {
+                // the remaining tasks are dropped together with the runtime,
+                // and nothing is printed as the mount didn't lead to the exit
+                debug!("terminated by a signal");
+                self.print_on_exit = false;
+                break;
+            }
+            // Keep running while exiting until the remaining tasks finish, as
+            // they may fail or ask for a passphrase, which cancels the exit.
+            if self.exit && self.tasks.is_empty() {
+                break;
+            }
             self.handle_events().wrap_err("handling events failed")?;
         }
         terminal.draw(|frame| {
-            frame.render_widget(
-                Paragraph::new(self.state_msg.as_deref().unwrap_or("exiting...")),
-                frame.size(),
-            )
+            frame.render_widget(Paragraph::new(self.final_message()), frame.size())
         })?;
 
-        // check remaining tasks
-        while let Some(task) = self.tasks.pop_front() {
-            match self.runtime.block_on(task)? {
-                Ok(msg) => self.handle_message(msg)?,
-                Err(err) => {
-                    self.state_msg = Some(format!("Error: {err}"));
-                    self.exit = false;
-                    return self.run(terminal);
-                }
-            }
+        Ok(())
+    }
+
+    /// What the last frame shows. It's drawn once the remaining tasks
+    /// finished and their messages were handled, so it's their outcome, e.g.
+    /// the mount point, unless some were abandoned, which `state_msg` would
+    /// still show as in progress.
+    fn final_message(&self) -> String {
+        let unfinished = self.abandoned + self.tasks.len();
+        if unfinished > 0 {
+            return format!(
+                "Exited without waiting for {unfinished} operation{}",
+                if unfinished == 1 { "" } else { "s" }
+            );
         }
+        self.state_msg
+            .clone()
+            .unwrap_or_else(|| "exiting...".to_string())
+    }
 
-        if !self.exit {
-            return self.run(terminal);
+    /// Exits once no key has been pressed for the configured idle timeout.
+    /// Time spent typing a passphrase doesn't count.
+    fn check_idle(&mut self) {
+        if let AppState::ReadingPassphrase { .. } = self.state {
+            self.last_input = Instant::now();
+            return;
+        }
+        let Some(timeout) = self.config.idle_timeout_secs else {
+            return;
+        };
+        if !self.exit && self.last_input.elapsed() >= Duration::from_secs(timeout) {
+            debug!("exiting after {timeout}s idle");
+            self.state_msg = Some(format!("Exiting after {timeout}s without input..."));
+            self.exit = true;
         }
+    }
 
-        Ok(())
+    fn announce_status(&mut self) {
+        let Some(file) = &mut self.announce else {
+            return;
+        };
+        if self.state_msg == self.announced {
+            return;
+        }
+        if let Some(msg) = &self.state_msg {
+            if let Err(err) = writeln!(file, "{msg}") {
+                warn!("failed to announce status: {err}");
+            }
+        }
+        self.announced.clone_from(&self.state_msg);
     }
 
     pub fn print_exit_mount_point(&self) {
@@ -134,303 +498,2417 @@ impl App {
         }
     }
 
+    /// `Scanning devices...` with a spinner while the devices are being
+    /// listed.
+    fn scanning(&self) -> Option<String> {
+        let scan = self.scan.as_ref()?;
+        if scan.task.is_finished() {
+            return None;
+        }
+        let frames: &[&str] = if self.is_ascii() {
+            &["|", "/", "-", "\\"]
+        } else {
+            &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]
+        };
+        let frame = frames[(scan.started.elapsed().as_millis() / 100) as usize % frames.len()];
+        match scan.total.load(Ordering::Relaxed) {
+            0 => Some(format!("{frame} Scanning devices...")),
+            total => Some(format!(
+                "{frame} Scanning devices... {}/{total}",
+                scan.done.load(Ordering::Relaxed)
+            )),
+        }
+    }
+
+    fn is_ascii(&self) -> bool {
+        self.config.ascii.unwrap_or(false)
+    }
+
+    /// The fields shown in the details popup and pane.
+    fn details_lines(&self, idx: usize) -> Vec<Line<'static>> {
+        let device = &self.gui_devices[idx];
+        let fields = [
+            ("Name", device.info.name.clone()),
+            ("Path", device.info.path.display().to_string()),
+            ("Hint name", device.info.hint_name.clone()),
+            ("Label", device.info.label.clone()),
+            ("UUID", device.info.uuid.clone()),
+            ("Filesystem", device.info.fstype.clone()),
+            ("Size", self.size_mode.format(device.info.size)),
+            ("Mount point", device.info.mount_point.clone()),
+            (
+                "Mounted by",
+                if device.info.mount_point.is_empty() {
+                    String::new()
+                } else if device.info.mounted_externally() {
+                    "fstab, mount or another user".to_string()
+                } else {
+                    "udisks2".to_string()
+                },
+            ),
+            (
+                "Usage",
+                device
+                    .info
+                    .usage
+                    .map(|usage| {
+                        format!(
+                            "{} of {} ({:.0}%)",
+                            self.size_mode.format(usage.used),
+                            self.size_mode.format(usage.total),
+                            usage.ratio() * 100.0
+                        )
+                    })
+                    .unwrap_or_default(),
+            ),
+            ("Status", device.status()),
+            ("Member of", device.member_of.clone().unwrap_or_default()),
+            (
+                "Read-only",
+                if device.read_only { "yes" } else { "no" }.to_string(),
+            ),
+            (
+                "Auto-mount",
+                if device.auto { "yes" } else { "no" }.to_string(),
+            ),
+        ];
+        let partition_fields = device.partition.iter().flat_map(|partition| {
+            [
+                ("Partition type", partition.type_name.clone()),
+                ("Partition name", partition.name.clone()),
+            ]
+        });
+        // e.g. the /dev/mapper path of an unlocked LUKS device
+        let preferred_field = (device.info.preferred_name != device.info.name)
+            .then(|| ("Preferred path", device.info.preferred_name.clone()));
+        let error_field = device.error.iter().map(|error| ("Error", error.clone()));
+        let removal_field = device
+            .drive
+            .as_ref()
+            .filter(|drive| drive.removable)
+            .map(|drive| {
+                let safe = match self.removal_blocker(drive) {
+                    Some(blocker) => format!("no, {blocker}"),
+                    None => "yes".to_string(),
+                };
+                ("Safe to remove", safe)
+            });
+        fields
+            .into_iter()
+            .chain(preferred_field)
+            .chain(partition_fields)
+            .chain(removal_field)
+            .chain(error_field)
+            .map(|(name, value)| Line::from(vec![format!("{name}: ").bold(), value.into()]))
+            .collect()
+    }
+
+    /// Why unplugging `drive` now could lose data, `None` if it's safe: none
+    /// of its filesystems are mounted, none of its encrypted devices are
+    /// unlocked and no operation is running.
+    fn removal_blocker(&self, drive: &DriveInfo) -> Option<String> {
+        let in_use = self.gui_devices.iter().find(|device| {
+            device.drive.as_ref().is_some_and(|d| d.path == drive.path)
+                && matches!(
+                    device.state,
+                    DeviceState::Mounted | DeviceState::UnmountedUnlocked
+                )
+        });
+        if let Some(device) = in_use {
+            return Some(match device.state {
+                DeviceState::Mounted => format!("{} is mounted", device.info.name),
+                _ => format!("{} is unlocked", device.info.name),
+            });
+        }
+        if !self.tasks.is_empty() {
+            return Some("an operation is running".to_string());
+        }
+        None
+    }
+
+    /// The row of the device at `idx` in the table.
+    fn device_row(&self, idx: usize, columns: &[Column], usage_width: u16) -> Row<'_> {
+        let d = &self.gui_devices[idx];
+        let row = Row::new(columns.iter().map(|column| {
+            match column {
+                Column::Name => Cell::new(
+                    d.info
+                        .display_name(&self.config.name_order, self.long_names),
+                ),
+                Column::Label => Cell::new(d.info.label.as_str()),
+                Column::MountPoint => Cell::new(d.info.mount_point.as_str()),
+                Column::Size => Cell::new(match &d.drive {
+                    Some(drive) if self.drive_size && d.partition.is_some() && drive.size > 0 => {
+                        format!(
+                            "{} / {} drive",
+                            self.size_mode.format(d.info.size),
+                            self.size_mode.format(drive.size)
+                        )
+                    }
+                    _ => self.size_mode.format(d.info.size),
+                }),
+                Column::Usage => Cell::new(
+                    d.info
+                        .usage
+                        .map(|usage| usage_bar(usage, usage_width))
+                        .unwrap_or_default(),
+                ),
+                Column::Used => Cell::new(
+                    d.info
+                        .usage
+                        .map(|usage| self.size_mode.format(usage.used))
+                        .unwrap_or_default(),
+                ),
+                Column::Fstype => Cell::new(d.info.fstype.as_str()),
+                Column::Uuid => Cell::new(d.info.uuid.as_str()),
+                Column::Bus => Cell::new(d.drive.as_ref().map_or("", |drive| drive.bus.as_str())),
+                Column::Status => {
+                    let mut status = format!("{} {}", d.state.glyph(self.is_ascii()), d.status());
+                    if d.read_only {
+                        status.push_str(" RO");
+                    }
+                    // mounted through fstab or by another user
+                    if d.info.mounted_externally() {
+                        status.push_str(" ext");
+                    }
+                    Cell::new(status).style(d.state.style())
+                }
+                Column::Auto => Cell::new(if d.auto { "A" } else { "" }),
+                Column::LastMounted => Cell::new(
+                    self.history
+                        .last_mounted(History::key(&d.info.uuid, &d.info.name))
+                        .map(format_ago)
+                        .unwrap_or_default(),
+                ),
+            }
+        }));
+        if self.is_hidden(idx) {
+            row.dark_gray()
+        } else {
+            row
+        }
+    }
+
+    /// `set`, or ASCII borders in ASCII mode.
+    fn border_set(&self, set: border::Set) -> border::Set {
+        if self.is_ascii() {
+            ASCII_BORDER
+        } else {
+            set
+        }
+    }
+
     fn render_frame(&self, frame: &mut Frame) {
-        frame.render_widget(self, frame.size())
+        frame.render_widget(self, frame.size());
+        if self.config.no_color {
+            for cell in &mut frame.buffer_mut().content {
+                cell.set_fg(Color::Reset).set_bg(Color::Reset);
+            }
+        }
     }
 
     fn handle_events(&mut self) -> Result<()> {
         if event::poll(Duration::from_millis(100))? {
-            match event::read()? {
+            let event = event::read()?;
+            if let Event::Key(_) | Event::Paste(_) = event {
+                self.last_input = Instant::now();
+            }
+            match event {
                 Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
                     self.handle_key_event(key_event)?;
                 }
+                Event::Paste(text) => self.handle_paste(&text),
                 _ => {}
             }
         };
         Ok(())
     }
 
-    fn handle_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
-        if self.reading_passphrase {
-            if self.passphrase.is_none() {
-                self.passphrase = Some("".to_string());
-            }
-            let passphrase = self.passphrase.as_mut().unwrap();
-            match key_event.code {
-                KeyCode::Char(c) => {
-                    passphrase.push(c);
-                }
-                KeyCode::Esc => {
-                    self.passphrase = None;
-                    self.reading_passphrase = false;
-                    self.state_msg = None;
-                }
-                KeyCode::Enter => {
-                    self.reading_passphrase = false;
-                    self.mount()?;
-                    if self.exit_after_passphrase {
-                        self.exit = true;
-                        self.exit_after_passphrase = false;
-                    }
-                }
-                KeyCode::Backspace => {
-                    passphrase.pop();
-                }
-                _ => {}
+    /// Appends pasted text to the passphrase or text being typed, if any.
+    fn handle_paste(&mut self, text: &str) {
+        let text = text.trim_end_matches(['\r', '\n']);
+        match &mut self.state {
+            AppState::ReadingPassphrase { .. } => {
+                self.passphrase
+                    .get_or_insert_with(String::new)
+                    .push_str(text);
             }
-            return Ok(());
-        }
-        match key_event.code {
-            KeyCode::Char('q') | KeyCode::Esc => self.exit(),
-            KeyCode::Char('j') | KeyCode::Down => self.next_device(),
-            KeyCode::Char('k') | KeyCode::Up => self.prev_device(),
-            KeyCode::Char('G') | KeyCode::End => self.last_device(),
-            KeyCode::Char('g') | KeyCode::Home => self.first_device(),
-            KeyCode::Char('m') => self.mount()?,
-            KeyCode::Char('u') => self.unmount()?,
-            KeyCode::Char('e') => self.eject()?,
-            KeyCode::Char('r') => self.refresh()?,
-            KeyCode::Enter => {
-                self.mount()?;
-                self.print_on_exit = true;
-                self.exit();
+            AppState::Input { input, error, .. } => {
+                input.push_str(text);
+                *error = None;
             }
             _ => {}
         }
-        Ok(())
-    }
-
-    fn exit(&mut self) {
-        self.exit = true;
     }
 
-    fn next_device(&mut self) {
-        if self.gui_devices.is_empty() {
-            return;
-        }
-
-        if self.gui_devices.len() - 1 > self.selected_device_index {
-            self.selected_device_index += 1;
+    /// Shows the popup for a finished task, or queues it until the current
+    /// one is closed, so it doesn't replace what is being typed.
+    fn show_popup(&mut self, state: AppState) {
+        if let AppState::DisksList = self.state {
+            self.state = state;
+        } else {
+            self.pending_states.push_back(state);
         }
     }
 
-    fn prev_device(&mut self) {
-        if self.selected_device_index > 0 {
-            self.selected_device_index -= 1;
-        }
+    /// Closes the current popup and every queued one.
+    fn close_all_popups(&mut self) {
+        drop(self.passphrase.take().map(|p| SecStr::new(p.into_bytes())));
+        self.pending_states.clear();
+        self.state = AppState::DisksList;
+        self.exit_after_passphrase = false;
+        self.state_msg = None;
     }
 
-    fn last_device(&mut self) {
-        if self.gui_devices.is_empty() {
-            return;
+    fn handle_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        if self.exit {
+            // quit without waiting for the remaining tasks
+            if let KeyCode::Char('q') | KeyCode::Esc = key_event.code {
+                for task in self.tasks.drain(..) {
+                    task.handle.abort();
+                    self.abandoned += 1;
+                }
+            }
+            return Ok(());
         }
-
-        self.selected_device_index = self.gui_devices.len() - 1;
-    }
-
-    fn first_device(&mut self) {
-        self.selected_device_index = 0;
-    }
-
-    fn handle_message(&mut self, msg: Message) -> Result<()> {
-        match msg {
-            Message::Devices(gui_devices, devices) => {
-                self.gui_devices = gui_devices.into();
-                self.devices = devices.into();
-                self.selected_device_index = 0;
-                self.exit_mount_point = None;
-                self.print_on_exit = false;
-                Ok(())
+        if key_event.code == KeyCode::Char('c')
+            && key_event.modifiers.contains(KeyModifiers::CONTROL)
+            && !matches!(self.state, AppState::DisksList)
+        {
+            self.close_all_popups();
+            return Ok(());
+        }
+        match self.state {
+            AppState::DisksList => {}
+            AppState::ReadingPassphrase { .. } => {
+                return self.handle_passphrase_key_event(key_event)
             }
-            Message::Mounted(idx, mount_point) => {
-                let device = &mut self.gui_devices[idx];
-                device.state = DeviceState::Mounted;
-                device.info.mount_point = mount_point.clone();
-                self.state_msg = Some(format!("Mounted {} at {}", device.info.name, mount_point));
-                self.exit_mount_point = Some(mount_point);
-                Ok(())
+            AppState::ChoosingMountOptions { .. } => {
+                return self.handle_mount_options_key_event(key_event)
             }
-            Message::Unmounted(idx) => {
-                let device = &mut self.gui_devices[idx];
-                device.state = DeviceState::Unmounted;
-                device.info.mount_point = String::new();
-                self.state_msg = Some(format!("Unmounted {}", device.info.name));
-                Ok(())
+            AppState::ChoosingSubvolume { .. } => {
+                return self.handle_subvolume_key_event(key_event)
             }
-            Message::Locked(idx) => {
-                let device = &mut self.gui_devices[idx];
-                device.state = DeviceState::Locked;
-                device.info.mount_point = String::new();
-                self.state_msg = Some(format!("Locked {}", device.info.name));
-                Ok(())
+            AppState::ChoosingEraseMethod { .. } => {
+                return self.handle_erase_method_key_event(key_event)
             }
-            Message::UnmountedAndLocked(idx, device_info) => {
-                let device = &mut self.gui_devices[idx];
-                device.info = device_info;
-                device.state = DeviceState::Locked;
-                self.state_msg = Some(format!("Unmounted and locked {}", device.info.name));
-                Ok(())
+            AppState::ConfirmingWipe { .. } => return self.handle_wipe_key_event(key_event),
+            AppState::ChoosingPartitionTable { .. } => {
+                return self.handle_partition_table_key_event(key_event)
             }
-            Message::UnlockedAndMounted(idx, mount_point, device_info) => {
-                let device = &mut self.gui_devices[idx];
-                device.info = device_info;
-                device.state = DeviceState::Mounted;
-                self.state_msg = Some(format!(
-                    "Unlocked and mounted {} at {}",
-                    device.info.name, mount_point
-                ));
-                self.exit_mount_point = Some(mount_point);
-                Ok(())
+            AppState::ConfirmingPartitionTable { .. } => {
+                return self.handle_confirm_partition_table_key_event(key_event)
             }
-            Message::AlreadyMounted(idx, mount_point) => {
-                let device = &mut self.gui_devices[idx];
-                device.state = DeviceState::Mounted;
-                device.info.mount_point = mount_point.clone();
-                self.state_msg = Some(format!(
-                    "Already mounted {} at {}",
-                    device.info.name, mount_point
-                ));
-                self.exit_mount_point = Some(mount_point);
-                Ok(())
+            AppState::Input { .. } => return self.handle_input_key_event(key_event),
+            AppState::Error { .. } => return self.handle_error_key_event(key_event),
+            AppState::Help { .. } => return self.handle_help_key_event(key_event),
+            AppState::Confirming { .. } => return self.handle_confirm_key_event(key_event),
+            AppState::Details { idx } => {
+                match key_event.code {
+                    KeyCode::Char('q') | KeyCode::Char('i') | KeyCode::Esc | KeyCode::Enter => {
+                        self.state = AppState::DisksList;
+                    }
+                    KeyCode::Char('y') => self.copy_device_path(idx),
+                    _ => {}
+                }
+                return Ok(());
             }
-            Message::AlreadyUnmounted(idx) => {
-                let device = &mut self.gui_devices[idx];
-                device.state = DeviceState::Unmounted;
-                device.info.mount_point = String::new();
-                self.state_msg = Some(format!("Already unmounted {}", device.info.name));
-                Ok(())
+        }
+        if self.jumping {
+            self.jumping = false;
+            self.state_msg = None;
+            match key_event.code {
+                KeyCode::Char('m') => self.jump_to(DeviceState::Mounted),
+                KeyCode::Char('l') => self.jump_to(DeviceState::Locked),
+                KeyCode::Char('u') => self.jump_to(DeviceState::UnmountedUnlocked),
+                _ => {}
             }
-            Message::AlreadyLocked(idx) => {
-                let device = &mut self.gui_devices[idx];
-                device.state = DeviceState::Locked;
-                device.info.mount_point = String::new();
-                self.state_msg = Some(format!("Already unmounted and locked {}", device.info.name));
-                Ok(())
+            return Ok(());
+        }
+        // any key other than a digit consumes the count
+        let count = self.count.take();
+        let repeat = count.unwrap_or(1).min(self.gui_devices.len());
+        match key_event.code {
+            KeyCode::Char(c @ '0'..='9') if count.is_some() || c != '0' => {
+                let digit = c as usize - '0' as usize;
+                self.count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                return Ok(());
             }
-            Message::PassphraseRequired(idx) => {
-                self.reading_passphrase = true;
-                self.selected_device_index = idx;
-                if self.exit {
-                    self.exit_after_passphrase = true;
+            KeyCode::Esc if count.is_some() => return Ok(()),
+            _ => {}
+        }
+        let Some(action) = key_of(key_event).and_then(|key| self.keymap.action(key)) else {
+            return Ok(());
+        };
+        match action {
+            Action::Quit => self.exit(),
+            Action::Down => (0..repeat).for_each(|_| self.next_device()),
+            Action::Up => (0..repeat).for_each(|_| self.prev_device()),
+            Action::Last => match count {
+                Some(n) => self.nth_device(n),
+                None => self.last_device(),
+            },
+            Action::Jump => {
+                self.jumping = true;
+                self.state_msg =
+                    Some("Jump to the next: m mounted, l locked, u unlocked".to_string());
+            }
+            Action::First => match count {
+                Some(n) => self.nth_device(n),
+                None => self.first_device(),
+            },
+            Action::Mount => self.mount_or_exit()?,
+            Action::MountWithOptions => self.choose_mount_options(),
+            Action::Unmount => self.unmount()?,
+            Action::Unlock => self.unlock(),
+            Action::UndoUnmount => self.undo_unmount()?,
+            Action::Eject => self.eject()?,
+            Action::EjectAll => self.eject_all(),
+            Action::LockAll => self.lock_all(),
+            Action::Reload => self.reload_device(),
+            Action::Refresh => self.refresh()?,
+            Action::Wipe => self.request_wipe()?,
+            Action::PartitionTable => self.request_partition_table(),
+            Action::BindMount => self.request_bind_mount(),
+            Action::MountAt => self.request_mount_at(),
+            Action::MountAs => self.request_mount_as(),
+            Action::Subvolume => self.request_subvolume()?,
+            Action::Resize => self.request_resize(),
+            Action::Check => self.request_check(false),
+            Action::Repair => self.request_check(true),
+            Action::TakeOwnership => self.request_take_ownership(),
+            Action::Label => self.request_set_label(),
+            Action::SizeUnits => self.size_mode = self.size_mode.next(),
+            Action::DriveSize => self.drive_size = !self.drive_size,
+            Action::LongNames => {
+                self.long_names = !self.long_names;
+                self.sort_devices();
+            }
+            Action::Sort => self.cycle_sort(),
+            Action::MountableOnly => self.toggle_filesystems_only(),
+            Action::Hide => self.toggle_hidden(),
+            Action::ShowHidden => self.toggle_show_hidden(),
+            Action::Details => self.show_details(),
+            Action::DetailsPane => self.details_pane = !self.details_pane,
+            Action::Diagnostics => self.diagnostics(),
+            Action::CopyPath => {
+                if !self.devices.is_empty() {
+                    self.copy_device_path(self.selected_device_index)
                 }
-                self.exit = false;
-                Ok(())
             }
-            Message::Ejected(idx) => {
-                self.refresh()?;
-                self.state_msg = Some(format!("Ejected {}", self.gui_devices[idx].info.name));
-                Ok(())
+            Action::Export => {
+                self.state = AppState::Input {
+                    action: InputAction::Export,
+                    input: String::new(),
+                    error: None,
+                };
             }
+            Action::ShowError => self.show_error(),
+            Action::Help => self.state = AppState::Help { scroll: 0 },
+            Action::Activate => match self.config.enter_action {
+                EnterAction::MountAndExit
+                    if self.config.confirm_exit
+                        && !self.config.stay
+                        && !self.devices.is_empty() =>
+                {
+                    self.state = AppState::Confirming {
+                        action: ConfirmAction::MountAndExit,
+                        message: format!(
+                            "Mount {} and exit?",
+                            self.gui_devices[self.selected_device_index].info.name
+                        ),
+                    };
+                }
+                EnterAction::MountAndExit => self.mount_and_exit()?,
+                EnterAction::Mount => self.mount()?,
+                EnterAction::Details => self.show_details(),
+                EnterAction::Open => {
+                    self.open_after_mount = true;
+                    self.mount()?;
+                }
+            },
         }
+        Ok(())
     }
 
-    fn mount(&mut self) -> Result<()> {
-        if self.devices.is_empty() {
-            return Ok(());
+    fn handle_passphrase_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        if self.passphrase.is_none() {
+            self.passphrase = Some("".to_string());
+        }
+        let passphrase = self.passphrase.as_mut().unwrap();
+        match key_event.code {
+            KeyCode::Char(c) => {
+                passphrase.push(c);
+            }
+            KeyCode::Esc => {
+                // Wrapping it in a SecStr zeroes it out when dropped.
+                drop(self.passphrase.take().map(|p| SecStr::new(p.into_bytes())));
+                self.state = AppState::DisksList;
+                self.state_msg = None;
+            }
+            KeyCode::Enter => {
+                let AppState::ReadingPassphrase { idx, options, .. } =
+                    std::mem::replace(&mut self.state, AppState::DisksList)
+                else {
+                    return Ok(());
+                };
+                self.selected_device_index = idx;
+                match options {
+                    Some(options) => self.mount_with_options(options)?,
+                    None => self.unlock(),
+                }
+                if self.exit_after_passphrase {
+                    self.exit = true;
+                    self.exit_after_passphrase = false;
+                }
+            }
+            KeyCode::Backspace => {
+                passphrase.pop();
+            }
+            _ => {}
         }
-
-        let idx = self.selected_device_index;
-        let devices = Arc::clone(&self.devices);
-        let passphrase = self.passphrase.take().map(|p| SecStr::new(p.into_bytes()));
-        self.spawn(async move {
-            let device = &devices[idx];
-            let msg = device.mount(idx, passphrase).await?;
-            Ok(msg)
-        });
-
-        self.state_msg = Some(format!("Mounting {}...", self.gui_devices[idx].info.name));
         Ok(())
     }
 
-    fn unmount(&mut self) -> Result<()> {
-        if self.devices.is_empty() {
+    fn handle_mount_options_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        let AppState::ChoosingMountOptions { selected } = &mut self.state else {
             return Ok(());
-        }
-
+        };
+        match key_event.code {
+            // the first entry is the default, so there is one more than presets
+            KeyCode::Char('j') | KeyCode::Down if *selected < self.config.mount_options.len() => {
+                *selected += 1;
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                *selected = selected.saturating_sub(1);
+            }
+            KeyCode::Enter => {
+                let options = match selected.checked_sub(1) {
+                    Some(preset) => self
+                        .config
+                        .mount_options
+                        .values()
+                        .nth(preset)
+                        .map(MountOptions::with_options)
+                        .unwrap_or_default(),
+                    None => MountOptions::default(),
+                };
+                self.state = AppState::DisksList;
+                self.mount_with_options(options)?;
+            }
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.state = AppState::DisksList;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_subvolume_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        let AppState::ChoosingSubvolume {
+            idx,
+            subvolumes,
+            selected,
+        } = &mut self.state
+        else {
+            return Ok(());
+        };
+        match key_event.code {
+            // the first entry is the default subvolume
+            KeyCode::Char('j') | KeyCode::Down if *selected < subvolumes.len() => {
+                *selected += 1;
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                *selected = selected.saturating_sub(1);
+            }
+            KeyCode::Enter => {
+                let options = match selected.checked_sub(1) {
+                    Some(i) => MountOptions::with_options(format!("subvol={}", subvolumes[i])),
+                    None => MountOptions::default(),
+                };
+                self.selected_device_index = *idx;
+                self.state = AppState::DisksList;
+                self.mount_with_options(options)?;
+            }
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.state = AppState::DisksList;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_erase_method_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        let AppState::ChoosingEraseMethod {
+            idx,
+            methods,
+            selected,
+        } = &mut self.state
+        else {
+            return Ok(());
+        };
+        match key_event.code {
+            KeyCode::Char('j') | KeyCode::Down if *selected + 1 < methods.len() => {
+                *selected += 1;
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                *selected = selected.saturating_sub(1);
+            }
+            KeyCode::Enter => {
+                self.state = AppState::ConfirmingWipe {
+                    idx: *idx,
+                    method: methods[*selected],
+                    input: String::new(),
+                };
+            }
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.state = AppState::DisksList;
+                self.state_msg = None;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_wipe_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        let AppState::ConfirmingWipe { idx, method, input } = &mut self.state else {
+            return Ok(());
+        };
+        match key_event.code {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Enter => {
+                let (idx, method) = (*idx, *method);
+                if *input == self.gui_devices[idx].info.name {
+                    self.state = AppState::DisksList;
+                    self.wipe(idx, method)?;
+                } else {
+                    input.clear();
+                }
+            }
+            KeyCode::Esc => {
+                self.state = AppState::DisksList;
+                self.state_msg = None;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_partition_table_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        let AppState::ChoosingPartitionTable {
+            idx,
+            disk,
+            selected,
+        } = &mut self.state
+        else {
+            return Ok(());
+        };
+        match key_event.code {
+            KeyCode::Char('j') | KeyCode::Down if *selected + 1 < PartitionTableType::ALL.len() => {
+                *selected += 1;
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                *selected = selected.saturating_sub(1);
+            }
+            KeyCode::Enter => {
+                self.state = AppState::ConfirmingPartitionTable {
+                    idx: *idx,
+                    disk: disk.clone(),
+                    table: PartitionTableType::ALL[*selected],
+                    input: String::new(),
+                };
+            }
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.state = AppState::DisksList;
+                self.state_msg = None;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_confirm_partition_table_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        let AppState::ConfirmingPartitionTable { input, disk, .. } = &mut self.state else {
+            return Ok(());
+        };
+        match key_event.code {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Enter if *input == disk.name => {
+                let AppState::ConfirmingPartitionTable {
+                    idx, disk, table, ..
+                } = std::mem::replace(&mut self.state, AppState::DisksList)
+                else {
+                    return Ok(());
+                };
+                self.create_partition_table(idx, disk, table);
+            }
+            KeyCode::Enter => input.clear(),
+            KeyCode::Esc => {
+                self.state = AppState::DisksList;
+                self.state_msg = None;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_confirm_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        match key_event.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => {
+                let AppState::Confirming { action, .. } =
+                    std::mem::replace(&mut self.state, AppState::DisksList)
+                else {
+                    return Ok(());
+                };
+                match action {
+                    ConfirmAction::Unmount(idx) => self.unmount_device(idx)?,
+                    ConfirmAction::Resize(idx, size) => self.resize(idx, size),
+                    ConfirmAction::Check { idx, repair } => self.check(idx, repair),
+                    ConfirmAction::TakeOwnership { idx, recursive } => {
+                        self.take_ownership(idx, recursive)
+                    }
+                    ConfirmAction::MountAndExit => self.mount_and_exit()?,
+                }
+            }
+            KeyCode::Char('r') => {
+                if let AppState::Confirming {
+                    action: ConfirmAction::TakeOwnership { idx, recursive },
+                    ..
+                } = self.state
+                {
+                    self.state = AppState::Confirming {
+                        action: ConfirmAction::TakeOwnership {
+                            idx,
+                            recursive: !recursive,
+                        },
+                        message: self.take_ownership_message(idx, !recursive),
+                    };
+                }
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Char('q') | KeyCode::Esc => {
+                self.state = AppState::DisksList;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_error_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        let AppState::Error { scroll } = &mut self.state else {
+            return Ok(());
+        };
+        match key_event.code {
+            KeyCode::Char('j') | KeyCode::Down => *scroll = scroll.saturating_add(1),
+            KeyCode::Char('k') | KeyCode::Up => *scroll = scroll.saturating_sub(1),
+            KeyCode::Char('g') | KeyCode::Home => *scroll = 0,
+            KeyCode::Char('q') | KeyCode::Char('E') | KeyCode::Esc | KeyCode::Enter => {
+                self.state = AppState::DisksList;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_help_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        let AppState::Help { scroll } = &mut self.state else {
+            return Ok(());
+        };
+        match key_event.code {
+            KeyCode::Char('j') | KeyCode::Down => *scroll = scroll.saturating_add(1),
+            KeyCode::Char('k') | KeyCode::Up => *scroll = scroll.saturating_sub(1),
+            KeyCode::Char('g') | KeyCode::Home => *scroll = 0,
+            KeyCode::Char('q') | KeyCode::Char('?') | KeyCode::Esc | KeyCode::Enter => {
+                self.state = AppState::DisksList;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_input_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        let AppState::Input { input, error, .. } = &mut self.state else {
+            return Ok(());
+        };
+        match key_event.code {
+            KeyCode::Char(c) => {
+                input.push(c);
+                *error = None;
+            }
+            KeyCode::Backspace => {
+                input.pop();
+                *error = None;
+            }
+            KeyCode::Enter => self.submit_input()?,
+            KeyCode::Esc => {
+                self.state = AppState::DisksList;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn submit_input(&mut self) -> Result<()> {
+        let AppState::Input {
+            action,
+            input,
+            error,
+        } = &mut self.state
+        else {
+            return Ok(());
+        };
+        match *action {
+            InputAction::BindMount(idx) => {
+                if !Path::new(input.as_str()).is_dir() {
+                    *error = Some(format!("{input} is not a directory"));
+                    return Ok(());
+                }
+                let source = self.gui_devices[idx].info.mount_point.clone();
+                let target = std::mem::take(input);
+                self.state = AppState::DisksList;
+                self.state_msg = Some(format!("Bind mounting {source} at {target}..."));
+                self.spawn_for(idx, async move {
+                    let bind_mount = BindMount::mount(source, target).await?;
+                    Ok(DeviceMessage::BindMounted(idx, bind_mount))
+                });
+            }
+            InputAction::MountAt(idx) => {
+                if let Err(err) = bind_mount::check_target(Path::new(input.as_str())) {
+                    *error = Some(err);
+                    return Ok(());
+                }
+                let info = &self.gui_devices[idx].info;
+                let device = info.path.clone();
+                let target = std::mem::take(input);
+                self.state_msg = Some(format!("Mounting {} at {target}...", info.name));
+                self.state = AppState::DisksList;
+                self.spawn_for(idx, async move {
+                    bind_mount::mount_at(&device, &target).await?;
+                    Ok(DeviceMessage::Mounted(idx, target))
+                });
+            }
+            InputAction::MountAs(idx) => {
+                let fstype = input.trim();
+                if fstype.is_empty()
+                    || !fstype
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+                {
+                    *error = Some(format!("{fstype:?} is not a filesystem type"));
+                    return Ok(());
+                }
+                let options = MountOptions {
+                    fstype: Some(fstype.to_string()),
+                    ..Default::default()
+                };
+                self.state = AppState::DisksList;
+                self.selected_device_index = idx;
+                self.mount_with_options(options)?;
+            }
+            InputAction::Resize(idx) => {
+                let size = match device::parse_size(input, self.gui_devices[idx].info.size) {
+                    Ok(size) if size >= MIB => size / MIB * MIB,
+                    Ok(_) => {
+                        *error = Some("the size must be at least 1 MiB".to_string());
+                        return Ok(());
+                    }
+                    Err(err) => {
+                        *error = Some(err);
+                        return Ok(());
+                    }
+                };
+                let info = &self.gui_devices[idx].info;
+                self.state = AppState::Confirming {
+                    action: ConfirmAction::Resize(idx, size),
+                    message: format!(
+                        "Resize {} from {} to {}? Interrupting the resize can destroy the \
+                         data on it, make sure it's backed up.",
+                        info.name,
+                        self.size_mode.format(info.size),
+                        self.size_mode.format(size)
+                    ),
+                };
+            }
+            InputAction::SetLabel(idx) => {
+                let fstype = &self.gui_devices[idx].info.fstype;
+                let label = match label::sanitize(fstype, input) {
+                    Ok(label) => label,
+                    Err(err) => {
+                        *error = Some(err);
+                        return Ok(());
+                    }
+                };
+                self.state = AppState::DisksList;
+                self.state_msg = Some(format!(
+                    "Setting label of {} to {label}...",
+                    self.gui_devices[idx].info.name
+                ));
+                let devices = Arc::clone(&self.devices);
+                let client = self.client.clone();
+                self.spawn_for(idx, async move {
+                    let device = &devices[idx];
+                    let msg = client
+                        .reconnect_on_disconnect(device.set_label(idx, &label))
+                        .await?;
+                    Ok(msg)
+                });
+            }
+            InputAction::Export => {
+                let devices = self.order.iter().map(|&idx| &self.gui_devices[idx]);
+                let count = self.order.len();
+                let msg = if input.is_empty() {
+                    let table = export::to_string(devices, Format::Tsv);
+                    match clipboard::copy(table.as_bytes()) {
+                        Ok(()) => format!("Copied {count} devices as TSV"),
+                        Err(err) => format!("Error: failed to copy the devices: {err}"),
+                    }
+                } else {
+                    let path = Path::new(input.as_str());
+                    let format = Format::for_path(path);
+                    if let Err(err) = fs::write(path, export::to_string(devices, format)) {
+                        *error = Some(format!("failed to write {input}: {err}"));
+                        return Ok(());
+                    }
+                    format!("Exported {count} devices to {input}")
+                };
+                self.state = AppState::DisksList;
+                self.state_msg = Some(msg);
+            }
+        }
+        Ok(())
+    }
+
+    fn exit(&mut self) {
+        self.exit = true;
+    }
+
+    fn is_visible(&self, idx: usize) -> bool {
+        let device = &self.gui_devices[idx];
+        if !self.show_hidden && self.is_hidden(idx) {
+            return false;
+        }
+        !self.filesystems_only
+            || !matches!(
+                device.state,
+                DeviceState::Locked
+                    | DeviceState::NoMedia
+                    | DeviceState::Member
+                    | DeviceState::Failed
+            )
+    }
+
+    fn is_hidden(&self, idx: usize) -> bool {
+        let info = &self.gui_devices[idx].info;
+        self.history.is_hidden(History::key(&info.uuid, &info.name))
+    }
+
+    /// Hides the selected device, also in later runs, or shows it again if it
+    /// was hidden.
+    fn toggle_hidden(&mut self) {
+        if self.devices.is_empty() {
+            return;
+        }
+
+        let idx = self.selected_device_index;
+        let hidden = !self.is_hidden(idx);
+        let info = &self.gui_devices[idx].info;
+        self.history
+            .set_hidden(History::key(&info.uuid, &info.name), hidden);
+        self.state_msg = Some(if hidden {
+            format!(
+                "Hid {}, {} to show hidden devices",
+                info.name,
+                self.keymap.press(Action::ShowHidden)
+            )
+        } else {
+            format!("{} is no longer hidden", info.name)
+        });
+        if let Err(err) = self.history.save() {
+            warn!("failed to save the hidden devices: {err:#}");
+        }
+        if !self.is_visible(idx) {
+            self.next_device();
+            self.prev_device();
+        }
+    }
+
+    fn toggle_show_hidden(&mut self) {
+        self.show_hidden = !self.show_hidden;
+        if !self.gui_devices.is_empty() && !self.is_visible(self.selected_device_index) {
+            self.first_device();
+        }
+        self.state_msg = Some(if self.show_hidden {
+            "Showing hidden devices".to_string()
+        } else {
+            "Not showing hidden devices".to_string()
+        });
+    }
+
+    fn toggle_filesystems_only(&mut self) {
+        self.filesystems_only = !self.filesystems_only;
+        if !self.gui_devices.is_empty() && !self.is_visible(self.selected_device_index) {
+            self.first_device();
+        }
+        self.state_msg = Some(if self.filesystems_only {
+            "Showing only mountable devices".to_string()
+        } else {
+            "Showing all devices".to_string()
+        });
+    }
+
+    fn cycle_sort(&mut self) {
+        self.sort = self.sort.next();
+        self.sort_devices();
+        self.state_msg = Some(
+            match self.sort {
+                SortMode::None => "Sorted by drive",
+                SortMode::Name => "Sorted by name",
+                SortMode::Size => "Sorted by size",
+                SortMode::State => "Sorted by state",
+            }
+            .to_string(),
+        );
+    }
+
+    /// Recomputes [`App::order`], which has to happen whenever a device
+    /// changes, e.g. its state when sorting by state. Ties keep the order
+    /// udisks2 lists the devices in.
+    fn sort_devices(&mut self) {
+        let devices = &self.gui_devices;
+        let mut order: Vec<usize> = (0..devices.len()).collect();
+        match self.sort {
+            SortMode::None => {}
+            SortMode::Name => order.sort_by_cached_key(|&i| {
+                devices[i]
+                    .info
+                    .display_name(&self.config.name_order, self.long_names)
+                    .to_lowercase()
+            }),
+            SortMode::Size => order.sort_by_key(|&i| Reverse(devices[i].info.size)),
+            SortMode::State => {
+                let state_order = &self.config.state_order;
+                order.sort_by_key(|&i| {
+                    state_order
+                        .iter()
+                        .position(|state| *state == devices[i].state)
+                        .unwrap_or(state_order.len())
+                });
+            }
+        }
+        self.order = order;
+    }
+
+    /// Position of the selected device in [`App::order`].
+    fn selected_position(&self) -> usize {
+        self.order
+            .iter()
+            .position(|&i| i == self.selected_device_index)
+            .unwrap_or(0)
+    }
+
+    /// Selects the next visible device in `state` after the selected one,
+    /// wrapping around to the first.
+    fn jump_to(&mut self, state: DeviceState) {
+        if self.order.is_empty() {
+            return;
+        }
+        let pos = self.selected_position();
+        let next = self.order[pos + 1..]
+            .iter()
+            .chain(&self.order[..=pos])
+            .copied()
+            .find(|&i| self.gui_devices[i].state == state && self.is_visible(i));
+        match next {
+            Some(idx) => self.selected_device_index = idx,
+            None => {
+                self.state_msg = Some(format!("No {} device", state.to_string().to_lowercase()))
+            }
+        }
+    }
+
+    fn next_device(&mut self) {
+        let pos = self.selected_position();
+        let next = self
+            .order
+            .iter()
+            .skip(pos + 1)
+            .copied()
+            .find(|&i| self.is_visible(i));
+        if let Some(idx) = next {
+            self.selected_device_index = idx;
+        }
+    }
+
+    fn prev_device(&mut self) {
+        let pos = self.selected_position();
+        let prev = self.order[..pos]
+            .iter()
+            .rev()
+            .copied()
+            .find(|&i| self.is_visible(i));
+        if let Some(idx) = prev {
+            self.selected_device_index = idx;
+        }
+    }
+
+    fn last_device(&mut self) {
+        let last = self
+            .order
+            .iter()
+            .rev()
+            .copied()
+            .find(|&i| self.is_visible(i));
+        if let Some(idx) = last {
+            self.selected_device_index = idx;
+        }
+    }
+
+    /// Selects the `n`th visible device counting from 1, or the last one if
+    /// there are fewer.
+    fn nth_device(&mut self, n: usize) {
+        self.first_device();
+        (1..n.min(self.gui_devices.len())).for_each(|_| self.next_device());
+    }
+
+    fn first_device(&mut self) {
+        self.selected_device_index = self
+            .order
+            .iter()
+            .copied()
+            .find(|&i| self.is_visible(i))
+            .unwrap_or(0);
+    }
+
+    fn handle_message(&mut self, msg: DeviceMessage) -> Result<()> {
+        match msg {
+            DeviceMessage::Devices(gui_devices, devices) => {
+                let old_devices = std::mem::replace(&mut self.devices, devices.into());
+                self.gui_devices = gui_devices.into();
+                // the popups follow their device to its new index, and close
+                // if it's gone
+                let state = std::mem::replace(&mut self.state, AppState::DisksList);
+                let reading_passphrase = matches!(state, AppState::ReadingPassphrase { .. });
+                match self.remap_state(state, &old_devices) {
+                    Some(state) => self.state = state,
+                    None if reading_passphrase => self.close_all_popups(),
+                    None => {}
+                }
+                self.pending_states = std::mem::take(&mut self.pending_states)
+                    .into_iter()
+                    .filter_map(|state| self.remap_state(state, &old_devices))
+                    .collect();
+                self.sort_devices();
+                let selected = old_devices
+                    .get(self.selected_device_index)
+                    .and_then(|device| self.device_index(&device.block_device().path));
+                match selected {
+                    Some(idx) if self.is_visible(idx) => self.selected_device_index = idx,
+                    _ => self.first_device(),
+                }
+                self.exit_mount_point = None;
+                self.print_on_exit = false;
+                // the indices may now point to other devices
+                self.last_unmounted = None;
+                self.last_mount = None;
+                self.scan = None;
+                Ok(())
+            }
+            DeviceMessage::Mounted(idx, mount_point) => {
+                self.record_mounted(idx);
+                let device = &mut self.gui_devices[idx];
+                device.state = DeviceState::Mounted;
+                device.info.set_mount_point(mount_point.clone());
+                self.state_msg = Some(format!("Mounted {} at {}", device.info.name, mount_point));
+                self.on_mounted(mount_point);
+                Ok(())
+            }
+            DeviceMessage::Unmounted(idx) => {
+                self.forget_bind_mounts(idx);
+                self.last_unmounted = Some(idx);
+                let device = &mut self.gui_devices[idx];
+                device.state = DeviceState::Unmounted;
+                device.info.set_mount_point(String::new());
+                self.state_msg = Some(format!("Unmounted {}", device.info.name));
+                Ok(())
+            }
+            DeviceMessage::Locked(idx) => {
+                let device = &mut self.gui_devices[idx];
+                device.state = DeviceState::Locked;
+                device.info.set_mount_point(String::new());
+                self.state_msg = Some(format!("Locked {}", device.info.name));
+                Ok(())
+            }
+            DeviceMessage::UnmountedAndLocked(idx, device_info) => {
+                self.forget_bind_mounts(idx);
+                self.last_unmounted = Some(idx);
+                let device = &mut self.gui_devices[idx];
+                device.info = device_info;
+                device.state = DeviceState::Locked;
+                self.state_msg = Some(format!("Unmounted and locked {}", device.info.name));
+                Ok(())
+            }
+            DeviceMessage::UnlockedAndMounted(idx, mount_point, device_info) => {
+                // both for the encrypted device and the filesystem inside, as
+                // the one shown depends on whether it's unlocked
+                self.record_mounted(idx);
+                let device = &mut self.gui_devices[idx];
+                device.info = device_info;
+                device.state = DeviceState::Mounted;
+                self.record_mounted(idx);
+                self.state_msg = Some(format!(
+                    "Unlocked and mounted {} at {}",
+                    self.gui_devices[idx].info.name, mount_point
+                ));
+                self.on_mounted(mount_point);
+                Ok(())
+            }
+            DeviceMessage::MountedUnlocked(idx, mount_point, device_info) => {
+                // same as above, the row switches to the cleartext device
+                self.record_mounted(idx);
+                let device = &mut self.gui_devices[idx];
+                device.info = device_info;
+                device.state = DeviceState::Mounted;
+                self.record_mounted(idx);
+                self.state_msg = Some(format!(
+                    "Mounted already unlocked {} at {}",
+                    self.gui_devices[idx].info.name, mount_point
+                ));
+                self.on_mounted(mount_point);
+                Ok(())
+            }
+            DeviceMessage::Unlocked(idx, device_info) => {
+                let device = &mut self.gui_devices[idx];
+                self.state_msg = Some(format!("Unlocked as {}", device_info.preferred_name));
+                device.info = device_info;
+                device.state = DeviceState::UnmountedUnlocked;
+                Ok(())
+            }
+            DeviceMessage::UnlockedContainer(idx, device_info) => {
+                let device = &mut self.gui_devices[idx];
+                device.info = device_info;
+                device.state = DeviceState::UnmountedUnlocked;
+                self.state_msg = Some(format!(
+                    "Unlocked {}, the filesystems inside are listed on their own",
+                    device.info.name
+                ));
+                self.get_or_refresh_devices();
+                Ok(())
+            }
+            DeviceMessage::LockedContainer(idx) => {
+                self.state_msg = Some(format!("Locked {}", self.gui_devices[idx].info.name));
+                self.get_or_refresh_devices();
+                Ok(())
+            }
+            DeviceMessage::AlreadyMounted(idx, mount_point) => {
+                let device = &mut self.gui_devices[idx];
+                device.state = DeviceState::Mounted;
+                device.info.set_mount_point(mount_point.clone());
+                self.state_msg = Some(format!(
+                    "Already mounted {} at {}",
+                    device.info.name, mount_point
+                ));
+                self.on_mounted(mount_point);
+                Ok(())
+            }
+            DeviceMessage::AlreadyUnmounted(idx) => {
+                let device = &mut self.gui_devices[idx];
+                device.state = DeviceState::Unmounted;
+                device.info.set_mount_point(String::new());
+                self.state_msg = Some(format!("Already unmounted {}", device.info.name));
+                Ok(())
+            }
+            DeviceMessage::AlreadyLocked(idx) => {
+                let device = &mut self.gui_devices[idx];
+                device.state = DeviceState::Locked;
+                device.info.set_mount_point(String::new());
+                self.state_msg = Some(format!("Already unmounted and locked {}", device.info.name));
+                Ok(())
+            }
+            DeviceMessage::PassphraseRequired(idx, options, tokens) => {
+                self.show_popup(AppState::ReadingPassphrase {
+                    idx,
+                    options,
+                    needs: luks::describe_tokens(&tokens),
+                });
+                if self.exit {
+                    self.exit_after_passphrase = true;
+                }
+                self.exit = false;
+                Ok(())
+            }
+            DeviceMessage::Flushed(idx) => {
+                self.eject_flushed(idx);
+                Ok(())
+            }
+            DeviceMessage::Ejected(idx) => {
+                self.state_msg = Some(format!("Ejected {}", self.gui_devices[idx].info.name));
+                self.refresh_device(idx);
+                Ok(())
+            }
+            DeviceMessage::EraseMethods(idx, methods) => {
+                self.show_popup(AppState::ChoosingEraseMethod {
+                    idx,
+                    methods,
+                    selected: 0,
+                });
+                self.state_msg = None;
+                Ok(())
+            }
+            DeviceMessage::LabelChanged(idx, label) => {
+                let device = &mut self.gui_devices[idx];
+                self.state_msg = Some(format!("Set label of {} to {}", device.info.name, label));
+                device.info.label = label;
+                Ok(())
+            }
+            DeviceMessage::BindMounted(_, bind_mount) => {
+                self.state_msg = Some(format!(
+                    "Bind mounted {} at {}",
+                    bind_mount.source, bind_mount.target
+                ));
+                self.bind_mounts.push(bind_mount);
+                Ok(())
+            }
+            DeviceMessage::Resized(idx, size) => {
+                self.state_msg = Some(format!(
+                    "Resized {} to {}",
+                    self.gui_devices[idx].info.name,
+                    self.size_mode.format(size)
+                ));
+                self.refresh_device(idx);
+                Ok(())
+            }
+            DeviceMessage::Checked(idx, consistent) => {
+                let name = &self.gui_devices[idx].info.name;
+                self.state_msg = Some(if consistent {
+                    format!("The filesystem on {name} is clean")
+                } else {
+                    format!(
+                        "The filesystem on {name} has errors, {} to repair it",
+                        self.keymap.press(Action::Repair)
+                    )
+                });
+                self.forget_bind_mounts(idx);
+                self.refresh_device(idx);
+                Ok(())
+            }
+            DeviceMessage::OwnershipTaken(idx, recursive) => {
+                let info = &self.gui_devices[idx].info;
+                self.state_msg = Some(if recursive {
+                    format!("You now own every file on {}", info.name)
+                } else {
+                    format!("You now own {}", info.mount_point)
+                });
+                Ok(())
+            }
+            DeviceMessage::Repaired(idx, repaired) => {
+                let name = &self.gui_devices[idx].info.name;
+                self.state_msg = Some(if repaired {
+                    format!("Repaired the filesystem on {name}")
+                } else {
+                    format!("Failed to repair the filesystem on {name}")
+                });
+                self.forget_bind_mounts(idx);
+                self.refresh_device(idx);
+                Ok(())
+            }
+            DeviceMessage::Subvolumes(idx, subvolumes) => {
+                self.state_msg = None;
+                self.show_popup(AppState::ChoosingSubvolume {
+                    idx,
+                    subvolumes,
+                    selected: 0,
+                });
+                Ok(())
+            }
+            DeviceMessage::Diagnostics(idx, diagnostics) => {
+                self.save_diagnostics(idx, &diagnostics);
+                Ok(())
+            }
+            DeviceMessage::WholeDisk(idx, disk) => {
+                self.show_popup(AppState::ChoosingPartitionTable {
+                    idx,
+                    disk,
+                    selected: 0,
+                });
+                Ok(())
+            }
+            DeviceMessage::PartitionTableCreated(_, name) => {
+                // the partitions on it are gone, which shifts the indices
+                self.state_msg = Some(format!("Created an empty partition table on {name}"));
+                self.get_or_refresh_devices();
+                Ok(())
+            }
+            DeviceMessage::Wiped(idx) => {
+                self.state_msg = Some(format!("Wiped {}", self.gui_devices[idx].info.name));
+                self.refresh_device(idx);
+                Ok(())
+            }
+            DeviceMessage::DeviceRefreshed(idx, Some(gui_device)) => {
+                self.gui_devices[idx] = gui_device;
+                Ok(())
+            }
+            DeviceMessage::DeviceRefreshed(_, None) => {
+                // the device changed too much to be refreshed in place, e.g. it
+                // no longer has a filesystem or its media was ejected
+                self.get_or_refresh_devices();
+                Ok(())
+            }
+            DeviceMessage::DrivesEjected(paths, errors) => {
+                let indices: Vec<usize> = paths
+                    .iter()
+                    .filter_map(|path| self.device_index(path))
+                    .collect();
+                for &idx in &indices {
+                    self.forget_bind_mounts(idx);
+                }
+                self.state_msg = Some(match (indices.is_empty(), errors.len()) {
+                    (true, 0) => "No removable drives to eject".to_string(),
+                    (false, 0) => "Ejected all removable drives".to_string(),
+                    (_, failed) => {
+                        self.last_error = Some(ErrorDetails {
+                            message: errors.join("\n\n"),
+                            dbus_name: None,
+                            kind: None,
+                        });
+                        format!(
+                            "Error: failed to eject {failed} drive(s) ({} for details)",
+                            self.keymap.press(Action::ShowError)
+                        )
+                    }
+                });
+                self.get_or_refresh_devices();
+                Ok(())
+            }
+            DeviceMessage::LockedAll(messages, errors) => {
+                let locked = messages.len();
+                for (path, mut msg) in messages {
+                    // the device may be gone, e.g. a container locked along
+                    // with the volumes inside it
+                    let Some(current) = self.device_index(&path) else {
+                        continue;
+                    };
+                    if let Some(idx) = msg.idx_mut() {
+                        *idx = current;
+                    }
+                    self.handle_message(msg)?;
+                }
+                // undoing would only remount one of them
+                self.last_unmounted = None;
+                self.state_msg = Some(match (locked, errors.len()) {
+                    (0, 0) => "No unlocked encrypted devices to lock".to_string(),
+                    (locked, 0) => format!("Locked {locked} encrypted device(s)"),
+                    (locked, failed) => {
+                        self.last_error = Some(ErrorDetails {
+                            message: errors.join("\n\n"),
+                            dbus_name: None,
+                            kind: None,
+                        });
+                        format!(
+                            "Error: locked {locked}, failed to lock {failed} device(s) ({} for \
+                             details)",
+                            self.keymap.press(Action::ShowError)
+                        )
+                    }
+                });
+                Ok(())
+            }
+        }
+    }
+
+    fn mount(&mut self) -> Result<()> {
+        self.mount_with_options(MountOptions::default())
+    }
+
+    /// Mounts the selected device, or exits like [`App::mount_and_exit`] when
+    /// it's the second press within [`Config::double_tap_ms`], leaving the
+    /// mount started by the first one to finish.
+    fn mount_or_exit(&mut self) -> Result<()> {
+        if self.devices.is_empty() {
+            return Ok(());
+        }
+
+        let idx = self.selected_device_index;
+        let window = Duration::from_millis(self.config.double_tap_ms);
+        match self.last_mount.take() {
+            Some((pressed, last)) if last == idx && pressed.elapsed() <= window => {
+                self.print_on_exit = true;
+                self.exit();
+                Ok(())
+            }
+            _ => {
+                self.last_mount = Some((Instant::now(), idx));
+                self.mount()
+            }
+        }
+    }
+
+    fn mount_and_exit(&mut self) -> Result<()> {
+        self.mount()?;
+        self.print_on_exit = true;
+        if !self.config.stay {
+            self.exit();
+        }
+        Ok(())
+    }
+
+    fn choose_mount_options(&mut self) {
+        if self.devices.is_empty() {
+            return;
+        }
+
+        self.state = AppState::ChoosingMountOptions { selected: 0 };
+    }
+
+    fn mount_with_options(&mut self, mut options: MountOptions) -> Result<()> {
+        if self.devices.is_empty() {
+            return Ok(());
+        }
+
+        let idx = self.selected_device_index;
+        if self.not_mountable(idx) {
+            return Ok(());
+        }
+        let info = &self.gui_devices[idx].info;
+        self.config.apply_mount_options(&mut options, &info.uuid);
+        self.state_msg = Some(match &options.fstype {
+            Some(fstype) => format!("Mounting {} as {fstype}...", info.name),
+            None => format!("Mounting {}...", info.name),
+        });
+        let devices = Arc::clone(&self.devices);
+        let passphrase = self.passphrase.take().map(|p| SecStr::new(p.into_bytes()));
+        let client = self.client.clone();
+        let retries = self.config.retries;
+        self.spawn_for(idx, async move {
+            let device = &devices[idx];
+            let msg = client
+                .retry_transient(retries, || device.mount(idx, passphrase.clone(), &options))
+                .await?;
+            Ok(msg)
+        });
+        Ok(())
+    }
+
+    /// Unlocks the selected encrypted device without mounting it, asking for
+    /// the passphrase first.
+    fn unlock(&mut self) {
+        if self.devices.is_empty() {
+            return;
+        }
+
+        let idx = self.selected_device_index;
+        if self.not_mountable(idx) {
+            return;
+        }
+        let device = &self.gui_devices[idx];
+        if device.state != DeviceState::Locked {
+            self.state_msg = Some(format!("{} isn't locked", device.info.name));
+            return;
+        }
+        self.state_msg = Some(format!("Unlocking {}...", device.info.name));
+        let devices = Arc::clone(&self.devices);
+        let passphrase = self.passphrase.take().map(|p| SecStr::new(p.into_bytes()));
+        let client = self.client.clone();
+        self.spawn_for(idx, async move {
+            let device = &devices[idx];
+            client
+                .retry_on_disconnect(|| device.unlock(idx, passphrase.clone()))
+                .await
+        });
+    }
+
+    /// Lists the subvolumes to choose one to mount for btrfs devices, just
+    /// mounts other ones.
+    fn request_subvolume(&mut self) -> Result<()> {
+        if self.devices.is_empty() {
+            return Ok(());
+        }
+
+        let idx = self.selected_device_index;
+        if self.gui_devices[idx].info.fstype != "btrfs" {
+            return self.mount();
+        }
+        let devices = Arc::clone(&self.devices);
+        let client = self.client.clone();
+        self.spawn_for(idx, async move {
+            let device = &devices[idx];
+            let msg = client
+                .retry_on_disconnect(|| device.btrfs_subvolumes(idx))
+                .await?;
+            Ok(msg)
+        });
+        self.state_msg = Some(format!(
+            "Listing the subvolumes of {}...",
+            self.gui_devices[idx].info.name
+        ));
+        Ok(())
+    }
+
+    /// Mounts the device unmounted last again.
+    fn undo_unmount(&mut self) -> Result<()> {
+        let Some(idx) = self.last_unmounted.take() else {
+            self.state_msg = Some("Nothing to remount".to_string());
+            return Ok(());
+        };
+        self.selected_device_index = idx;
+        self.mount()
+    }
+
+    /// Whether the device is an empty drive or a RAID or LVM member, saying
+    /// so instead of trying to mount or unmount it.
+    fn not_mountable(&mut self, idx: usize) -> bool {
+        let device = &self.gui_devices[idx];
+        self.state_msg = Some(match device.state {
+            DeviceState::NoMedia => format!(
+                "There is no media in {}, insert one and {}",
+                device.info.name,
+                self.keymap.press(Action::Refresh)
+            ),
+            DeviceState::Member => match &device.member_of {
+                Some(group) => format!(
+                    "{} is part of {group}, mount that instead",
+                    device.info.name
+                ),
+                None => format!(
+                    "{} is part of a RAID array or LVM volume group, mount that instead",
+                    device.info.name
+                ),
+            },
+            _ => return false,
+        });
+        true
+    }
+
+    fn unmount(&mut self) -> Result<()> {
+        if self.devices.is_empty() {
+            return Ok(());
+        }
+
+        let idx = self.selected_device_index;
+        if self.not_mountable(idx) {
+            return Ok(());
+        }
+        let mount_point = &self.gui_devices[idx].info.mount_point;
+        if is_cwd_inside(mount_point) {
+            self.state = AppState::Confirming {
+                action: ConfirmAction::Unmount(idx),
+                message: format!(
+                    "You appear to be inside {mount_point}, so unmounting will likely fail \
+                     because it is busy. Unmount anyway?"
+                ),
+            };
+            return Ok(());
+        }
+        self.unmount_device(idx)
+    }
+
+    fn unmount_device(&mut self, idx: usize) -> Result<()> {
+        let devices = Arc::clone(&self.devices);
+        let client = self.client.clone();
+        let bind_mounts = self.device_bind_mounts(idx);
+        let mount_point = self.gui_devices[idx].info.mount_point.clone();
+        let retries = self.config.retries;
+        self.spawn_for(idx, async move {
+            for bind_mount in bind_mounts {
+                bind_mount.unmount().await?;
+            }
+            let device = &devices[idx];
+            match client
+                .retry_transient(retries, || device.unmount(idx))
+                .await
+            {
+                Err(err)
+                    if !mount_point.is_empty()
+                        && UdisksError::of(&err) == Some(UdisksError::Busy) =>
+                {
+                    let processes = processes::using(Path::new(&mount_point));
+                    if processes.is_empty() {
+                        return Err(err);
+                    }
+                    let processes: Vec<String> = processes
+                        .iter()
+                        .map(|process| format!("{} ({})", process.name, process.pid))
+                        .collect();
+                    Err(err.wrap_err(format!(
+                        "{mount_point} is in use by {}",
+                        processes.join(", ")
+                    )))
+                }
+                result => result,
+            }
+        });
+
+        self.state_msg = Some(format!(
+            "Unmounting {}...",
+            &self.gui_devices[idx].info.name
+        ));
+        Ok(())
+    }
+
+    fn record_mounted(&mut self, idx: usize) {
+        let info = &self.gui_devices[idx].info;
+        self.history
+            .record_mounted(History::key(&info.uuid, &info.name));
+        if let Err(err) = self.history.save() {
+            warn!("failed to save the mount history: {err:#}");
+        }
+    }
+
+    /// Rings the bell or runs the command configured for when an operation
+    /// succeeds or fails.
+    fn notify(&self, success: bool) {
+        if self.config.bell {
+            // the terminal is on stderr, and a bell doesn't disturb what's drawn
+            let bells = if success { "\x07" } else { "\x07\x07" };
+            if let Err(err) = write!(io::stderr(), "{bells}") {
+                warn!("failed to ring the bell: {err}");
+            }
+        }
+        let command = if success {
+            &self.config.success_command
+        } else {
+            &self.config.error_command
+        };
+        if let Some(command) = command {
+            let result = Command::new("sh")
+                .args(["-c", command])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+            if let Err(err) = result {
+                warn!("failed to run {command:?}: {err}");
+            }
+        }
+    }
+
+    fn on_mounted(&mut self, mount_point: String) {
+        if self.open_after_mount {
+            self.open_after_mount = false;
+            let result = Command::new("xdg-open")
+                .arg(&mount_point)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+            if let Err(err) = result {
+                self.state_msg = Some(format!("Error: failed to open {mount_point}: {err}"));
+            }
+        }
+        self.exit_mount_point = Some(mount_point);
+    }
+
+    fn show_error(&mut self) {
+        if self.last_error.is_some() {
+            self.state = AppState::Error { scroll: 0 };
+        }
+    }
+
+    fn show_details(&mut self) {
+        if self.devices.is_empty() {
+            return;
+        }
+
+        self.state = AppState::Details {
+            idx: self.selected_device_index,
+        };
+    }
+
+    fn diagnostics(&mut self) {
+        if self.devices.is_empty() {
+            return;
+        }
+
+        let idx = self.selected_device_index;
+        let devices = Arc::clone(&self.devices);
+        let client = self.client.clone();
+        self.spawn_for(idx, async move {
+            let device = &devices[idx];
+            let msg = client
+                .retry_on_disconnect(|| device.diagnostics(idx))
+                .await?;
+            Ok(msg)
+        });
+    }
+
+    /// Copies `diagnostics` to the clipboard and writes them to a file in the
+    /// runtime directory, as the clipboard doesn't work in every terminal.
+    fn save_diagnostics(&mut self, idx: usize, diagnostics: &str) {
+        let name = &self.gui_devices[idx].info.name;
+        let file_name = name.rsplit('/').next().unwrap_or(name);
+        // only the runtime directory is private, while a predictable name in
+        // /tmp could be a symlink planted by another user
+        let saved = env::var_os("XDG_RUNTIME_DIR")
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| {
+                let path = PathBuf::from(dir).join(format!("udiskstui-{file_name}.txt"));
+                fs::write(&path, diagnostics).map(|()| path)
+            });
+        let copied = clipboard::copy(diagnostics.as_bytes());
+        self.state_msg = Some(match (copied, saved) {
+            (Ok(()), Some(Ok(path))) => format!(
+                "Copied the diagnostics of {name}, also saved to {}",
+                path.display()
+            ),
+            (Err(_), Some(Ok(path))) => {
+                format!("Saved the diagnostics of {name} to {}", path.display())
+            }
+            (Ok(()), _) => format!("Copied the diagnostics of {name}"),
+            (Err(err), _) => format!("Error: failed to copy the diagnostics: {err}"),
+        });
+    }
+
+    /// Copies the device path, e.g. `/dev/sdb1`, to the clipboard.
+    fn copy_device_path(&mut self, idx: usize) {
+        let path = &self.gui_devices[idx].info.path;
+        self.state_msg = Some(match clipboard::copy(path.as_os_str().as_bytes()) {
+            Ok(()) => format!("Copied {}", path.display()),
+            Err(err) => format!("Error: failed to copy {}: {err}", path.display()),
+        });
+    }
+
+    fn request_set_label(&mut self) {
+        if self.devices.is_empty() {
+            return;
+        }
+
+        let idx = self.selected_device_index;
+        self.state = AppState::Input {
+            action: InputAction::SetLabel(idx),
+            input: self.gui_devices[idx].info.label.clone(),
+            error: None,
+        };
+    }
+
+    /// Checks or repairs the filesystem, asking first when repairing or when
+    /// it has to be unmounted.
+    fn request_check(&mut self, repair: bool) {
+        if self.devices.is_empty() {
+            return;
+        }
+
+        let idx = self.selected_device_index;
+        let device = &self.gui_devices[idx];
+        if let DeviceState::Locked = device.state {
+            self.state_msg = Some(format!("{} is locked, unlock it first", device.info.name));
+            return;
+        }
+        let mounted = !device.info.mount_point.is_empty();
+        let message = match (repair, mounted) {
+            (false, false) => return self.check(idx, false),
+            (false, true) => format!("{} is mounted, unmount it and check it?", device.info.name),
+            (true, false) => format!(
+                "Repair the filesystem on {}? Interrupting the repair can make it worse.",
+                device.info.name
+            ),
+            (true, true) => format!(
+                "Unmount {} and repair its filesystem? Interrupting the repair can make it \
+                 worse.",
+                device.info.name
+            ),
+        };
+        self.state = AppState::Confirming {
+            action: ConfirmAction::Check { idx, repair },
+            message,
+        };
+    }
+
+    fn check(&mut self, idx: usize, repair: bool) {
+        let devices = Arc::clone(&self.devices);
+        let client = self.client.clone();
+        let bind_mounts = self.device_bind_mounts(idx);
+        self.spawn_for(idx, async move {
+            for bind_mount in bind_mounts {
+                bind_mount.unmount().await?;
+            }
+            let device = &devices[idx];
+            // a repair mustn't run twice, while a check can be repeated
+            if repair {
+                client
+                    .reconnect_on_disconnect(device.check(idx, repair))
+                    .await
+            } else {
+                client
+                    .retry_on_disconnect(|| device.check(idx, repair))
+                    .await
+            }
+        });
+        self.state_msg = Some(format!(
+            "{} {}...",
+            if repair { "Repairing" } else { "Checking" },
+            self.gui_devices[idx].info.name
+        ));
+    }
+
+    fn request_take_ownership(&mut self) {
+        if self.devices.is_empty() {
+            return;
+        }
+
+        let idx = self.selected_device_index;
+        let device = &self.gui_devices[idx];
+        if device.state != DeviceState::Mounted {
+            self.state_msg = Some(format!(
+                "{} is not mounted, mount it first",
+                device.info.name
+            ));
+            return;
+        }
+        self.state = AppState::Confirming {
+            action: ConfirmAction::TakeOwnership {
+                idx,
+                recursive: false,
+            },
+            message: self.take_ownership_message(idx, false),
+        };
+    }
+
+    fn take_ownership_message(&self, idx: usize, recursive: bool) -> String {
+        let info = &self.gui_devices[idx].info;
+        if recursive {
+            format!(
+                "Make your user the owner of EVERY file and directory on {}, replacing the \
+                 owners other users and programs rely on? This can't be undone and takes a \
+                 while on big filesystems.",
+                info.name
+            )
+        } else {
+            format!(
+                "Make your user the owner of {}, only the top directory? Press r to change \
+                 the owner of every file on it instead.",
+                info.mount_point
+            )
+        }
+    }
+
+    fn take_ownership(&mut self, idx: usize, recursive: bool) {
+        let devices = Arc::clone(&self.devices);
+        let client = self.client.clone();
+        self.spawn_for(idx, async move {
+            let device = &devices[idx];
+            let msg = client
+                .retry_on_disconnect(|| device.take_ownership(idx, recursive))
+                .await?;
+            Ok(msg)
+        });
+        self.state_msg = Some(if recursive {
+            format!(
+                "Taking ownership of every file on {}, this can take a while...",
+                self.gui_devices[idx].info.name
+            )
+        } else {
+            format!(
+                "Taking ownership of {}...",
+                self.gui_devices[idx].info.mount_point
+            )
+        });
+    }
+
+    fn request_mount_as(&mut self) {
+        if self.devices.is_empty() {
+            return;
+        }
+
+        let idx = self.selected_device_index;
+        let info = &self.gui_devices[idx].info;
+        let fstype = self.config.fstypes.get(&info.uuid).unwrap_or(&info.fstype);
+        self.state = AppState::Input {
+            action: InputAction::MountAs(idx),
+            input: fstype.clone(),
+            error: None,
+        };
+    }
+
+    fn request_resize(&mut self) {
+        if self.devices.is_empty() {
+            return;
+        }
+
+        let idx = self.selected_device_index;
+        self.state = AppState::Input {
+            action: InputAction::Resize(idx),
+            input: self.size_mode.format(self.gui_devices[idx].info.size),
+            error: None,
+        };
+    }
+
+    fn resize(&mut self, idx: usize, size: u64) {
+        let devices = Arc::clone(&self.devices);
+        let client = self.client.clone();
+        self.spawn_for(idx, async move {
+            let device = &devices[idx];
+            let msg = client
+                .reconnect_on_disconnect(device.resize(idx, size))
+                .await?;
+            Ok(msg)
+        });
+        self.state_msg = Some(format!("Resizing {}...", self.gui_devices[idx].info.name));
+    }
+
+    fn request_mount_at(&mut self) {
+        if self.devices.is_empty() {
+            return;
+        }
+
+        let idx = self.selected_device_index;
+        let device = &self.gui_devices[idx];
+        if let DeviceState::Locked = device.state {
+            self.state_msg = Some(format!("{} is locked, unlock it first", device.info.name));
+            return;
+        }
+        self.state = AppState::Input {
+            action: InputAction::MountAt(idx),
+            input: String::new(),
+            error: None,
+        };
+    }
+
+    fn request_bind_mount(&mut self) {
+        if self.devices.is_empty() {
+            return;
+        }
+
+        let idx = self.selected_device_index;
+        let device = &self.gui_devices[idx];
+        if device.info.mount_point.is_empty() {
+            self.state_msg = Some(format!("{} is not mounted", device.info.name));
+            return;
+        }
+        self.state = AppState::Input {
+            action: InputAction::BindMount(idx),
+            input: String::new(),
+            error: None,
+        };
+    }
+
+    /// Bind mounts of the filesystem of the device at `idx`, which have to be
+    /// unmounted before the device itself.
+    fn device_bind_mounts(&self, idx: usize) -> Vec<BindMount> {
+        let mount_point = &self.gui_devices[idx].info.mount_point;
+        self.bind_mounts
+            .iter()
+            .filter(|bind_mount| !mount_point.is_empty() && bind_mount.source == *mount_point)
+            .cloned()
+            .collect()
+    }
+
+    fn forget_bind_mounts(&mut self, idx: usize) {
+        let mount_point = &self.gui_devices[idx].info.mount_point;
+        self.bind_mounts
+            .retain(|bind_mount| bind_mount.source != *mount_point);
+    }
+
+    /// Flushes the pending writes, then ejects the selected device once
+    /// [`DeviceMessage::Flushed`] arrives.
+    fn eject(&mut self) -> Result<()> {
+        if self.devices.is_empty() {
+            return Ok(());
+        }
+
         let idx = self.selected_device_index;
+        let name = &self.gui_devices[idx].info.name;
+        self.state_msg = Some(match pending_writes() {
+            Some(bytes) if bytes > 0 => format!(
+                "Flushing {} of pending writes before ejecting {name}...",
+                self.size_mode.format(bytes)
+            ),
+            _ => format!("Flushing writes before ejecting {name}..."),
+        });
+        self.spawn_for(idx, async move {
+            flush().await?;
+            Ok(DeviceMessage::Flushed(idx))
+        });
+        Ok(())
+    }
+
+    fn eject_flushed(&mut self, idx: usize) {
+        let devices = Arc::clone(&self.devices);
+        let client = self.client.clone();
+        self.spawn_for(idx, async move {
+            let device = &devices[idx];
+            let msg = client.retry_on_disconnect(|| device.eject(idx)).await?;
+            Ok(msg)
+        });
+
+        self.state_msg = Some(format!("Ejecting {}...", &self.gui_devices[idx].info.name));
+    }
+
+    /// Unmounts, locks and ejects every removable drive, one task per drive.
+    fn eject_all(&mut self) {
+        let devices = Arc::clone(&self.devices);
+        let client = self.client.clone();
+        let bind_mounts: Vec<(usize, Vec<BindMount>)> = (0..self.gui_devices.len())
+            .map(|idx| (idx, self.device_bind_mounts(idx)))
+            .filter(|(_, bind_mounts)| !bind_mounts.is_empty())
+            .collect();
+        self.spawn(async move {
+            let drives = client
+                .retry_on_disconnect(|| removable_drives(&client, &devices))
+                .await?;
+            let mut tasks = JoinSet::new();
+            for (drive, indices) in drives {
+                let devices = Arc::clone(&devices);
+                let client = client.clone();
+                let bind_mounts: Vec<BindMount> = bind_mounts
+                    .iter()
+                    .filter(|(idx, _)| indices.contains(idx))
+                    .flat_map(|(_, bind_mounts)| bind_mounts.iter().cloned())
+                    .collect();
+                tasks.spawn(async move {
+                    for bind_mount in &bind_mounts {
+                        bind_mount.unmount().await?;
+                    }
+                    client
+                        .retry_on_disconnect(|| eject_drive(&client, &devices, &drive, &indices))
+                        .await?;
+                    let paths: Vec<OwnedObjectPath> = indices
+                        .iter()
+                        .map(|&idx| devices[idx].block_device().path.clone())
+                        .collect();
+                    Ok::<_, color_eyre::Report>(paths)
+                });
+            }
+
+            let mut ejected = Vec::new();
+            let mut errors = Vec::new();
+            while let Some(result) = tasks.join_next().await {
+                match result? {
+                    Ok(paths) => ejected.extend(paths),
+                    Err(err) => errors.push(format!("{err:#}")),
+                }
+            }
+            Ok(DeviceMessage::DrivesEjected(ejected, errors))
+        });
+
+        self.state_msg = Some("Ejecting all removable drives...".to_string());
+    }
+
+    /// Unmounts and locks every unlocked encrypted device at once, e.g.
+    /// before stepping away, carrying on past the ones that fail.
+    fn lock_all(&mut self) {
+        let targets: Vec<(usize, String, Vec<BindMount>)> = (0..self.devices.len())
+            .filter(|&idx| {
+                self.devices[idx].block_device().kind == BlockDeviceKind::Encrypted
+                    && matches!(
+                        self.gui_devices[idx].state,
+                        DeviceState::Mounted | DeviceState::UnmountedUnlocked
+                    )
+            })
+            .map(|idx| {
+                let name = self.gui_devices[idx].info.name.clone();
+                (idx, name, self.device_bind_mounts(idx))
+            })
+            .collect();
         let devices = Arc::clone(&self.devices);
+        let client = self.client.clone();
+        self.state_msg = Some(format!("Locking {} encrypted device(s)...", targets.len()));
         self.spawn(async move {
+            let mut tasks = JoinSet::new();
+            for (idx, name, bind_mounts) in targets {
+                let devices = Arc::clone(&devices);
+                let client = client.clone();
+                tasks.spawn(async move {
+                    let device = &devices[idx];
+                    let result = async {
+                        for bind_mount in &bind_mounts {
+                            bind_mount.unmount().await?;
+                        }
+                        client.retry_on_disconnect(|| device.unmount(idx)).await
+                    }
+                    .await;
+                    result
+                        .map(|msg| (device.block_device().path.clone(), msg))
+                        .map_err(|err| format!("{name}: {err:#}"))
+                });
+            }
+
+            let mut locked = Vec::new();
+            let mut errors = Vec::new();
+            while let Some(result) = tasks.join_next().await {
+                match result? {
+                    Ok(msg) => locked.push(msg),
+                    Err(err) => errors.push(err),
+                }
+            }
+            Ok(DeviceMessage::LockedAll(locked, errors))
+        });
+    }
+
+    fn request_wipe(&mut self) -> Result<()> {
+        if self.devices.is_empty() {
+            return Ok(());
+        }
+
+        let idx = self.selected_device_index;
+        let devices = Arc::clone(&self.devices);
+        let client = self.client.clone();
+        self.spawn_for(idx, async move {
+            let device = &devices[idx];
+            let msg = client
+                .retry_on_disconnect(|| device.erase_methods(idx))
+                .await?;
+            Ok(msg)
+        });
+
+        Ok(())
+    }
+
+    fn wipe(&mut self, idx: usize, method: EraseMethod) -> Result<()> {
+        let devices = Arc::clone(&self.devices);
+        let client = self.client.clone();
+        self.spawn_for(idx, async move {
             let device = &devices[idx];
-            let msg = device.unmount(idx).await?;
+            let msg = client
+                .reconnect_on_disconnect(device.wipe(idx, method))
+                .await?;
             Ok(msg)
         });
 
         self.state_msg = Some(format!(
-            "Unmounting {}...",
-            &self.gui_devices[idx].info.name
+            "Wiping {} ({})... this may take a while",
+            &self.gui_devices[idx].info.name,
+            method.description()
         ));
         Ok(())
     }
 
-    fn eject(&mut self) -> Result<()> {
+    fn request_partition_table(&mut self) {
         if self.devices.is_empty() {
-            return Ok(());
+            return;
         }
 
         let idx = self.selected_device_index;
         let devices = Arc::clone(&self.devices);
-        self.spawn(async move {
+        let client = self.client.clone();
+        self.spawn_for(idx, async move {
             let device = &devices[idx];
-            let msg = device.eject(idx).await?;
-            Ok(msg)
+            client.retry_on_disconnect(|| device.whole_disk(idx)).await
         });
+    }
 
-        self.state_msg = Some(format!("Ejecting {}...", &self.gui_devices[idx].info.name));
-        Ok(())
+    fn create_partition_table(&mut self, idx: usize, disk: Disk, table: PartitionTableType) {
+        self.state_msg = Some(format!(
+            "Creating a {} partition table on {}...",
+            table.name(),
+            disk.name
+        ));
+        let devices = Arc::clone(&self.devices);
+        let client = self.client.clone();
+        self.spawn_for(idx, async move {
+            let device = &devices[idx];
+            client
+                .reconnect_on_disconnect(device.create_partition_table(idx, &disk, table))
+                .await
+        });
     }
 
     fn refresh(&mut self) -> Result<()> {
         self.selected_device_index = 0;
+        self.last_unmounted = None;
         self.passphrase = None;
-        self.reading_passphrase = false;
+        self.state = AppState::DisksList;
         self.state_msg = None;
         self.exit = false;
         self.exit_after_passphrase = false;
         self.exit_mount_point = None;
         self.print_on_exit = false;
+        self.open_after_mount = false;
         self.get_or_refresh_devices();
         Ok(())
     }
 
-    fn get_or_refresh_devices(&mut self) {
+    /// Re-queries the selected device from scratch, without the cached
+    /// properties, for when they went stale.
+    fn reload_device(&mut self) {
+        if self.devices.is_empty() {
+            return;
+        }
+
+        let idx = self.selected_device_index;
+        let devices = Arc::clone(&self.devices);
         let client = self.client.clone();
-        self.spawn(async move {
-            let block_devices = client.get_block_devices().await?;
-            let mut devices = Vec::with_capacity(block_devices.len());
-            let mut gui_devices = Vec::with_capacity(block_devices.len());
+        self.spawn_for(idx, async move {
+            let device = &devices[idx];
+            client
+                .retry_on_disconnect(|| device.forget_cached())
+                .await?;
+            let gui_device = client
+                .retry_on_disconnect(|| GuiDevice::new(&client, device.block_device()))
+                .await
+                .ok();
+            Ok(DeviceMessage::DeviceRefreshed(idx, gui_device))
+        });
+    }
 
-            for block_device in block_devices {
-                gui_devices.push(GuiDevice::new(&client, &block_device).await?);
-                devices.push(Device::new(&client, block_device).await?);
-            }
+    /// Re-queries the device at `idx` without rescanning every device.
+    fn refresh_device(&mut self, idx: usize) {
+        let devices = Arc::clone(&self.devices);
+        let client = self.client.clone();
+        self.spawn_for(idx, async move {
+            let block_device = devices[idx].block_device();
+            let gui_device = client
+                .retry_on_disconnect(|| GuiDevice::new(&client, block_device))
+                .await
+                .inspect_err(|err| warn!("failed to refresh {}: {err:#}", block_device.path))
+                .ok();
+            Ok(DeviceMessage::DeviceRefreshed(idx, gui_device))
+        });
+    }
 
-            Ok(Message::Devices(gui_devices, devices))
+    /// Lists the devices, querying them concurrently so a slow one doesn't
+    /// hold up the others, and showing the ones that fail or time out as
+    /// [`DeviceState::Failed`] instead of failing the whole list.
+    fn get_or_refresh_devices(&mut self) {
+        let client = self.client.clone();
+        let done = Arc::new(AtomicUsize::new(0));
+        let total = Arc::new(AtomicUsize::new(0));
+        let (task_done, task_total) = (Arc::clone(&done), Arc::clone(&total));
+        let kinds = self.config.device_kinds.clone();
+        self.spawn(async move {
+            let mut devices = client.retry_on_disconnect(|| list_devices(&client)).await?;
+            devices.retain(|device| kinds.contains(&device.block_device().kind));
+            task_total.store(devices.len(), Ordering::Relaxed);
+            let gui_devices = join_all(devices.iter().map(|device| async {
+                let block_device = device.block_device();
+                let result = tokio::time::timeout(
+                    DEVICE_TIMEOUT,
+                    client.retry_on_disconnect(|| GuiDevice::new(&client, block_device)),
+                )
+                .await;
+                task_done.fetch_add(1, Ordering::Relaxed);
+                let err = match result {
+                    Ok(Ok(gui_device)) => return gui_device,
+                    Ok(Err(err)) => format!("{err:#}"),
+                    Err(_) => format!(
+                        "udisks2 didn't answer within {} seconds",
+                        DEVICE_TIMEOUT.as_secs()
+                    ),
+                };
+                warn!("failed to query {}: {err}", block_device.path);
+                GuiDevice::failed(block_device, err)
+            }))
+            .await;
+            Ok(DeviceMessage::Devices(gui_devices, devices))
+        });
+        self.scan = self.tasks.back().map(|task| Scan {
+            task: task.handle.abort_handle(),
+            started: Instant::now(),
+            done,
+            total,
         });
     }
 
     fn spawn<F>(&mut self, task: F)
     where
-        F: Future<Output = Result<Message>> + Send + 'static,
+        F: Future<Output = Result<DeviceMessage>> + Send + 'static,
     {
-        self.tasks.push_back(self.runtime.spawn(task));
+        self.tasks.push_back(Task {
+            handle: self.runtime.spawn(task),
+            path: None,
+        });
+        debug!("spawned task, {} pending", self.tasks.len());
+    }
+
+    /// Like [`App::spawn`], for a task about the device at `idx`, whose
+    /// message is pointed at wherever that device is once the task finishes.
+    fn spawn_for<F>(&mut self, idx: usize, task: F)
+    where
+        F: Future<Output = Result<DeviceMessage>> + Send + 'static,
+    {
+        let path = self.devices[idx].block_device().path.clone();
+        debug!("spawned task for {path}, {} pending", self.tasks.len() + 1);
+        self.tasks.push_back(Task {
+            handle: self.runtime.spawn(task),
+            path: Some(path),
+        });
+    }
+
+    /// `state` pointed at the index its device has in the current list, or
+    /// `None` if the device is gone.
+    fn remap_state(&self, mut state: AppState, old_devices: &[Device]) -> Option<AppState> {
+        if let Some(idx) = state.idx_mut() {
+            *idx = self.device_index(&old_devices.get(*idx)?.block_device().path)?;
+        }
+        Some(state)
+    }
+
+    /// The current index of the device at `path`, `None` if it's gone.
+    fn device_index(&self, path: &OwnedObjectPath) -> Option<usize> {
+        self.devices
+            .iter()
+            .position(|device| &device.block_device().path == path)
     }
 
+    /// Handles the finished tasks in the order they were spawned, leaving
+    /// the running ones in place.
     fn check_finished_tasks(&mut self) -> Result<()> {
-        for _ in 0..self.tasks.len() {
-            if let Some(task) = self.tasks.pop_front() {
-                if task.is_finished() {
-                    match self.runtime.block_on(task)? {
-                        Ok(msg) => self.handle_message(msg)?,
-                        Err(err) => {
-                            self.state_msg = Some(format!("Error: {err}"));
-                            self.exit = false;
+        while let Some(pos) = self.tasks.iter().position(|task| task.handle.is_finished()) {
+            let Some(task) = self.tasks.remove(pos) else {
+                break;
+            };
+            match self.runtime.block_on(task.handle)? {
+                Ok(mut msg) => {
+                    if let (Some(path), Some(idx)) = (&task.path, msg.idx_mut()) {
+                        match self.device_index(path) {
+                            Some(current) => *idx = current,
+                            None => {
+                                debug!("{path} is gone, dropping {msg:?}");
+                                continue;
+                            }
                         }
                     }
-                } else {
-                    self.tasks.push_back(task)
+                    debug!("task finished: {msg:?}");
+                    let completed = matches!(
+                        msg,
+                        DeviceMessage::Mounted(..)
+                            | DeviceMessage::UnlockedAndMounted(..)
+                            | DeviceMessage::MountedUnlocked(..)
+                            | DeviceMessage::Unmounted(..)
+                            | DeviceMessage::UnmountedAndLocked(..)
+                            | DeviceMessage::Locked(..)
+                            | DeviceMessage::LockedContainer(..)
+                            | DeviceMessage::Ejected(..)
+                            | DeviceMessage::DrivesEjected(..)
+                            | DeviceMessage::LockedAll(..)
+                    );
+                    self.handle_message(msg)?;
+                    self.sort_devices();
+                    if completed {
+                        self.notify(true);
+                    }
+                    // the selected device may have been hidden, e.g. by
+                    // locking it while only mountable ones are shown, so
+                    // select the visible one before it, or after it if
+                    // there's none
+                    if !self.gui_devices.is_empty() && !self.is_visible(self.selected_device_index)
+                    {
+                        self.next_device();
+                        self.prev_device();
+                    }
+                }
+                Err(err) => {
+                    error!("task failed: {err:#}");
+                    self.state_msg = Some(format!(
+                        "Error: {err} ({} for details)",
+                        self.keymap.press(Action::ShowError)
+                    ));
+                    self.last_error = Some(ErrorDetails {
+                        message: err
+                            .chain()
+                            .map(|err| err.to_string())
+                            .collect::<Vec<_>>()
+                            .join("\n\nCaused by: "),
+                        dbus_name: dbus_error_name(&err),
+                        kind: UdisksError::of(&err),
+                    });
+                    self.exit = false;
+                    self.open_after_mount = false;
+                    self.notify(false);
                 }
-            } else {
-                break;
             }
         }
         Ok(())
@@ -439,179 +2917,911 @@ impl App {
 
 impl Widget for &App {
     fn render(self, area: Rect, buf: &mut Buffer) {
+        let compact = self.config.compact;
         let layout = Layout::default()
             .direction(Direction::Vertical)
-            .constraints([
-                Constraint::Fill(1),
-                Constraint::Length(3),
-                Constraint::Length(2),
-            ])
+            .constraints(if compact {
+                [
+                    Constraint::Fill(1),
+                    Constraint::Length(1),
+                    Constraint::Length(1),
+                ]
+            } else {
+                [
+                    Constraint::Fill(1),
+                    Constraint::Length(3),
+                    Constraint::Length(2),
+                ]
+            })
             .split(area);
 
-        let header = Row::new(
-            ["Name", "Label", "Mount Point", "Size", "Status"]
-                .into_iter()
-                .map(Cell::from),
-        )
-        .blue();
-        let mut devices_rows: Vec<Row> = self
-            .gui_devices
-            .iter()
-            .map(|d| {
-                Row::new([
-                    Cell::new(d.info.name.as_str()),
-                    Cell::new(d.info.label.as_str()),
-                    Cell::new(d.info.mount_point.as_str()),
-                    Cell::new(d.info.size.as_str()),
-                    Cell::new(d.state.to_string()),
+        // The details of the selected device go next to the table, below it
+        // when the terminal is too narrow, or over it when it's too small.
+        let details = (self.details_pane && !self.gui_devices.is_empty())
+            .then(|| self.details_lines(self.selected_device_index));
+        let (table_area, details_area) = match &details {
+            Some(_) if layout[0].width >= 2 * DETAILS_PANE_WIDTH + 20 => {
+                let [table, details] = Layout::horizontal([
+                    Constraint::Fill(1),
+                    Constraint::Length(DETAILS_PANE_WIDTH),
                 ])
+                .areas(layout[0]);
+                (table, Some(details))
+            }
+            Some(lines) if layout[0].height >= lines.len() as u16 + 2 + MIN_TABLE_HEIGHT => {
+                let [table, details] = Layout::vertical([
+                    Constraint::Fill(1),
+                    Constraint::Length(lines.len() as u16 + 2),
+                ])
+                .areas(layout[0]);
+                (table, Some(details))
+            }
+            Some(lines) => (
+                layout[0],
+                Some(popup_area(area, 70, lines.len() as u16 + 2)),
+            ),
+            None => (layout[0], None),
+        };
+
+        let mut columns = self.config.columns.clone();
+        if compact && columns.iter().any(Column::is_essential) {
+            columns.retain(Column::is_essential);
+        }
+        let header = Row::new(columns.iter().map(|column| Cell::from(column.title()))).blue();
+        let widths: Vec<Constraint> = columns
+            .iter()
+            .map(|column| match column {
+                // room for e.g. `32.0 GB / 256.1 GB drive`
+                Column::Size if self.drive_size => Constraint::Max(28),
+                column => column.width(),
             })
             .collect();
-        let mut rows = vec![Row::new([Cell::default(); 0])];
-        rows.append(&mut devices_rows);
-        let widths = [
-            Constraint::Fill(1),
-            Constraint::Fill(1),
-            Constraint::Fill(1),
-            Constraint::Max(10),
-            Constraint::Max(10),
-        ];
-        let mut state = TableState::new().with_selected(self.selected_device_index + 1);
-        StatefulWidget::render(
-            Table::new(rows, widths)
-                .header(header)
-                .highlight_style(Style::new().blue().add_modifier(Modifier::REVERSED)),
-            layout[0],
-            buf,
-            &mut state,
-        );
+        // the same layout the table uses, to fit the usage bars to their column
+        let usage_width = columns
+            .iter()
+            .position(|column| *column == Column::Usage)
+            .map_or(0, |i| {
+                Layout::horizontal(widths.iter().copied())
+                    .flex(Flex::Start)
+                    .spacing(1)
+                    .split(table_area)[i]
+                    .width
+            });
+
+        // A header row is shown above each group of devices on the same drive,
+        // which shifts the selected row down by the headers above it. Sorting
+        // otherwise mixes up the drives, so they only group the unsorted list.
+        enum Entry<'a> {
+            Drive(Option<&'a DriveInfo>),
+            Device(usize),
+        }
+        let mut entries = vec![];
+        let mut selected_row = None;
+        let mut prev_drive = None;
+        for &idx in &self.order {
+            if !self.is_visible(idx) {
+                continue;
+            }
+            let d = &self.gui_devices[idx];
+            if self.sort == SortMode::None && prev_drive.replace(&d.drive) != Some(&d.drive) {
+                entries.push(Entry::Drive(d.drive.as_ref()));
+            }
+            if idx == self.selected_device_index {
+                selected_row = Some(entries.len());
+            }
+            entries.push(Entry::Device(idx));
+        }
+        let any_visible = entries
+            .iter()
+            .any(|entry| matches!(entry, Entry::Device(_)));
+
+        // Only the rows in view are built, scrolled like the table itself
+        // would, keeping the selected row at the bottom once it's below the
+        // first screenful.
+        let header_height = if compact { 0 } else { 2 };
+        let height = (table_area.height.saturating_sub(header_height) as usize).max(1);
+        let offset = selected_row.map_or(0, |row| (row + 1).saturating_sub(height));
+        let rows = entries
+            .iter()
+            .skip(offset)
+            .take(height)
+            .map(|entry| match *entry {
+                Entry::Drive(drive) => {
+                    let name = match drive {
+                        Some(drive) if drive.removable && self.removal_blocker(drive).is_none() => {
+                            format!("{} (safe to remove)", drive.name)
+                        }
+                        Some(drive) => drive.name.clone(),
+                        None => "Other devices".to_string(),
+                    };
+                    Row::new([Cell::new(name)]).dark_gray().italic()
+                }
+                Entry::Device(idx) => self.device_row(idx, &columns, usage_width),
+            });
+        let mut state = TableState::new().with_selected(selected_row.map(|row| row - offset));
+        let mut table = Table::new(rows, widths);
+        if !compact {
+            table = table.header(header.bottom_margin(1));
+        }
+        table = table.highlight_style(Style::new().blue().add_modifier(Modifier::REVERSED));
+        if self.config.no_color || self.is_ascii() {
+            table = table.highlight_symbol("> ");
+        }
+        StatefulWidget::render(table, table_area, buf, &mut state);
 
-        if let Some(msg) = self.state_msg.as_deref() {
+        if let (Some(lines), Some(details_area)) = (details, details_area) {
+            Clear.render(details_area, buf);
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: false })
+                .block(
+                    Block::new()
+                        .title(" Details ")
+                        .title_alignment(Alignment::Center)
+                        .borders(Borders::ALL)
+                        .border_set(self.border_set(border::PLAIN)),
+                )
+                .render(details_area, buf);
+        }
+
+        if !any_visible {
+            let msg = if let Some(scanning) = self.scanning() {
+                scanning
+            } else if self.gui_devices.is_empty() {
+                format!(
+                    "No mountable devices found, {} to refresh",
+                    self.keymap.press(Action::Refresh)
+                )
+            } else if self.filesystems_only {
+                format!(
+                    "No devices can be mounted right away, {} to show all",
+                    self.keymap.press(Action::MountableOnly)
+                )
+            } else {
+                format!(
+                    "All devices are hidden, {} to show them",
+                    self.keymap.press(Action::ShowHidden)
+                )
+            };
+            let [_, line, _] = Layout::vertical([
+                Constraint::Fill(1),
+                Constraint::Length(1),
+                Constraint::Fill(1),
+            ])
+            .areas(table_area);
             Paragraph::new(msg)
-                .block(Block::default().borders(Borders::ALL))
-                .render(layout[1], buf);
-        }
-        Text::from(vec![
-            Line::from(vec![
-                "m".bold().blue(),
-                " Mount".into(),
-                " | ".dark_gray(),
-                "u".bold().blue(),
-                " Unmount".into(),
-                " | ".dark_gray(),
-                "e".bold().blue(),
-                " Eject".into(),
-                " | ".dark_gray(),
-                "r".bold().blue(),
-                " Refresh".into(),
-            ]),
-            Line::from(vec![
-                "<Enter>".bold().blue(),
-                " Mount and exit printing mount point".into(),
-                " | ".dark_gray(),
-                "q".bold().blue(),
-                " Quit".into(),
-            ]),
-        ])
+                .alignment(Alignment::Center)
+                .dark_gray()
+                .render(line, buf);
+        }
+
+        let scanning = self.scanning().filter(|_| any_visible);
+        let state_msg = if self.client.is_reconnecting() {
+            Some("Connection to D-Bus lost, reconnecting...")
+        } else {
+            self.state_msg.as_deref().or(scanning.as_deref())
+        };
+        if let Some(msg) = state_msg {
+            let mut paragraph = Paragraph::new(msg);
+            if !compact {
+                paragraph = paragraph.block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_set(self.border_set(border::PLAIN)),
+                );
+            }
+            paragraph.render(layout[1], buf);
+        }
+        // compact mode only has room for the first line of hints
+        let hint_lines = if compact { 1 } else { 2 };
+        Text::from(
+            self.footer_hints()
+                .into_iter()
+                .take(hint_lines)
+                .map(|hints| {
+                    let mut spans = vec![];
+                    for (i, (key, action)) in hints.into_iter().enumerate() {
+                        if i > 0 {
+                            spans.push(" | ".dark_gray());
+                        }
+                        spans.push(key.bold().blue());
+                        spans.push(format!(" {action}").into());
+                    }
+                    Line::from(spans)
+                })
+                .collect::<Vec<_>>(),
+        )
         .alignment(Alignment::Center)
         .render(layout[2], buf);
 
-        if self.reading_passphrase {
-            let popup_layout = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints([
-                    Constraint::Fill(1),
-                    Constraint::Length(46),
-                    Constraint::Fill(1),
-                ])
-                .split(area);
-            let popup_layout = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Fill(1),
-                    Constraint::Length(4),
-                    Constraint::Fill(2),
+        if self.exit && !self.tasks.is_empty() {
+            let lines = vec![
+                Line::from(format!(
+                    "Finishing {} operation{}...",
+                    self.tasks.len(),
+                    if self.tasks.len() == 1 { "" } else { "s" }
+                )),
+                Line::from(vec!["q".bold().blue(), " Quit without waiting".into()]),
+            ];
+            let popup_area = popup_area(area, 46, lines.len() as u16 + 2);
+            Clear.render(popup_area, buf);
+            Paragraph::new(lines)
+                .alignment(Alignment::Center)
+                .block(
+                    Block::new()
+                        .title(" Exiting ")
+                        .title_alignment(Alignment::Center)
+                        .bold()
+                        .borders(Borders::ALL)
+                        .border_set(self.border_set(border::THICK)),
+                )
+                .render(popup_area, buf);
+            return;
+        }
+
+        match &self.state {
+            AppState::DisksList => {}
+            AppState::ReadingPassphrase {
+                idx,
+                options,
+                needs,
+            } => {
+                let width = if needs.is_some() { 60 } else { 46 };
+                // What the passphrase is for, as popups queue up and it may be
+                // another device than the selected one
+                let purpose = self.gui_devices.get(*idx).map(|device| {
+                    let verb = if options.is_some() { "mount" } else { "unlock" };
+                    match device.info.label.as_str() {
+                        "" => format!("To {verb} {}", device.info.name),
+                        label => format!("To {verb} {} ({label})", device.info.name),
+                    }
+                });
+                let purpose_height = purpose.as_ref().map_or(0, |purpose| {
+                    purpose.chars().count().div_ceil(width as usize - 2) as u16
+                });
+                let mut lines = vec![];
+                if let Some(purpose) = purpose {
+                    lines.push(Line::raw(purpose));
+                }
+                if let Some(needs) = needs {
+                    lines.push(Line::raw(format!(
+                        "This volume is enrolled to be unlocked with {needs}, which udisks2 can't use. Enter a passphrase instead, or unlock it with systemd-cryptsetup."
+                    )));
+                    lines.push(Line::raw(""));
+                }
+                // The note wraps to 3 lines at this width.
+                let height = purpose_height + if needs.is_some() { 7 } else { 3 };
+                let popup_area = popup_area(area, width, height);
+                // Only the tail fits when the passphrase is longer than the
+                // popup, leaving room for the caret.
+                let len = self.passphrase.as_ref().map_or(0, |p| p.chars().count());
+                let masked = "*".repeat(len.min(popup_area.width.saturating_sub(3) as usize));
+                lines.push(Line::from(vec![masked.into(), "_".slow_blink()]));
+                Clear.render(popup_area, buf);
+                Paragraph::new(lines)
+                    .wrap(Wrap { trim: true })
+                    .block(
+                        Block::new()
+                            .title(" Enter passphrase for unlocking device ")
+                            .title_alignment(Alignment::Center)
+                            .bold()
+                            .borders(Borders::ALL)
+                            .border_set(self.border_set(border::THICK)),
+                    )
+                    .render(popup_area, buf);
+            }
+            AppState::ChoosingMountOptions { selected } => {
+                let mut lines = vec![];
+                let presets = self
+                    .config
+                    .mount_options
+                    .iter()
+                    .map(|(name, options)| format!("{name} ({options})"));
+                for (i, preset) in std::iter::once("(default)".to_string())
+                    .chain(presets)
+                    .enumerate()
+                {
+                    if i == *selected {
+                        lines.push(Line::styled(preset, Style::new().reversed()));
+                    } else {
+                        lines.push(Line::from(preset));
+                    }
+                }
+                let popup_area = popup_area(area, 50, lines.len() as u16 + 2);
+                Clear.render(popup_area, buf);
+                Paragraph::new(lines)
+                    .alignment(Alignment::Center)
+                    .block(
+                        Block::new()
+                            .title(" Mount with options ")
+                            .title_alignment(Alignment::Center)
+                            .bold()
+                            .borders(Borders::ALL)
+                            .border_set(self.border_set(border::THICK)),
+                    )
+                    .render(popup_area, buf);
+            }
+            AppState::ChoosingSubvolume {
+                idx,
+                subvolumes,
+                selected,
+            } => {
+                let lines: Vec<Line> = std::iter::once("(default)")
+                    .chain(subvolumes.iter().map(String::as_str))
+                    .enumerate()
+                    .map(|(i, subvolume)| {
+                        if i == *selected {
+                            Line::styled(subvolume, Style::new().reversed())
+                        } else {
+                            Line::from(subvolume)
+                        }
+                    })
+                    .collect();
+                let popup_area = popup_area(area, 50, lines.len() as u16 + 2);
+                // keep the selected one visible when they don't all fit
+                let scroll = (*selected as u16).saturating_sub(popup_area.height.saturating_sub(3));
+                Clear.render(popup_area, buf);
+                Paragraph::new(lines)
+                    .alignment(Alignment::Center)
+                    .scroll((scroll, 0))
+                    .block(
+                        Block::new()
+                            .title(format!(
+                                " Mount a subvolume of {} ",
+                                self.gui_devices[*idx].info.name
+                            ))
+                            .title_alignment(Alignment::Center)
+                            .bold()
+                            .borders(Borders::ALL)
+                            .border_set(self.border_set(border::THICK)),
+                    )
+                    .render(popup_area, buf);
+            }
+            AppState::ChoosingEraseMethod {
+                idx,
+                methods,
+                selected,
+            } => {
+                let device = &self.gui_devices[*idx];
+                let mut lines = vec![
+                    Line::from(format!(
+                        "{} ({})",
+                        device.info.name,
+                        self.size_mode.format(device.info.size)
+                    )),
+                    Line::default(),
+                ];
+                for (i, method) in methods.iter().enumerate() {
+                    if i == *selected {
+                        lines.push(Line::styled(method.description(), Style::new().reversed()));
+                    } else {
+                        lines.push(Line::from(method.description()));
+                    }
+                }
+                let popup_area = popup_area(area, 50, lines.len() as u16 + 2);
+                Clear.render(popup_area, buf);
+                Paragraph::new(lines)
+                    .alignment(Alignment::Center)
+                    .block(
+                        Block::new()
+                            .title(" Choose erase method ")
+                            .title_alignment(Alignment::Center)
+                            .bold()
+                            .borders(Borders::ALL)
+                            .border_set(self.border_set(border::THICK)),
+                    )
+                    .render(popup_area, buf);
+            }
+            AppState::Input {
+                action,
+                input,
+                error,
+            } => {
+                let mut lines = vec![Line::from(vec![input.as_str().into(), "_".slow_blink()])];
+                if let Some(error) = error {
+                    lines.push(Line::styled(error.as_str(), Style::new().red()));
+                }
+                let popup_area = popup_area(area, 60, lines.len() as u16 + 2);
+                Clear.render(popup_area, buf);
+                Paragraph::new(lines)
+                    .block(
+                        Block::new()
+                            .title(action.title())
+                            .title_alignment(Alignment::Center)
+                            .bold()
+                            .borders(Borders::ALL)
+                            .border_set(self.border_set(border::THICK)),
+                    )
+                    .render(popup_area, buf);
+            }
+            AppState::Error { scroll } => {
+                let Some(error) = &self.last_error else {
+                    return;
+                };
+                let mut lines = vec![];
+                if let Some(dbus_name) = &error.dbus_name {
+                    lines.push(Line::styled(dbus_name.as_str(), Style::new().bold()));
+                }
+                if let Some(hint) = error.kind.and_then(UdisksError::hint) {
+                    lines.push(Line::from(format!("Hint: {hint}")));
+                }
+                if error.dbus_name.is_some() || error.kind.is_some() {
+                    lines.push(Line::default());
+                }
+                lines.extend(error.message.lines().map(Line::from));
+                let popup_area = popup_area(
+                    area,
+                    area.width.saturating_sub(4).min(80),
+                    area.height.saturating_sub(4).min(20),
+                );
+                Clear.render(popup_area, buf);
+                Paragraph::new(lines)
+                    .wrap(Wrap { trim: false })
+                    .scroll((*scroll, 0))
+                    .block(
+                        Block::new()
+                            .title(" Error ")
+                            .title_alignment(Alignment::Center)
+                            .bold()
+                            .red()
+                            .borders(Borders::ALL)
+                            .border_set(self.border_set(border::THICK)),
+                    )
+                    .render(popup_area, buf);
+            }
+            AppState::Help { scroll } => {
+                let lines: Vec<Line> = Action::ALL
+                    .into_iter()
+                    .map(|action| {
+                        let keys: Vec<String> = self
+                            .keymap
+                            .keys(action)
+                            .iter()
+                            .map(Key::to_string)
+                            .collect();
+                        Line::from(vec![
+                            format!("{:>14} ", keys.join(" ")).bold().blue(),
+                            action.description().into(),
+                        ])
+                    })
+                    .collect();
+                let popup_area = popup_area(
+                    area,
+                    area.width.saturating_sub(4).min(60),
+                    area.height.saturating_sub(4).min(lines.len() as u16 + 2),
+                );
+                Clear.render(popup_area, buf);
+                Paragraph::new(lines)
+                    .scroll((*scroll, 0))
+                    .block(
+                        Block::new()
+                            .title(" Keys ")
+                            .title_alignment(Alignment::Center)
+                            .bold()
+                            .borders(Borders::ALL)
+                            .border_set(self.border_set(border::THICK)),
+                    )
+                    .render(popup_area, buf);
+            }
+            AppState::Confirming { message, .. } => {
+                let popup_area = popup_area(area, 60, 6);
+                Clear.render(popup_area, buf);
+                Paragraph::new(vec![
+                    Line::from(message.as_str()),
+                    Line::from(vec![
+                        "y".bold().blue(),
+                        " Yes | ".into(),
+                        "n".bold().blue(),
+                        " No".into(),
+                    ]),
                 ])
-                .split(popup_layout[1]);
-            Clear.render(popup_layout[1], buf);
-            Block::new()
-                .title(" Enter passphrase for unlocking device ")
-                .title_alignment(Alignment::Center)
-                .bold()
-                .borders(Borders::ALL)
-                .border_set(border::THICK)
-                .render(popup_layout[1], buf);
+                .alignment(Alignment::Center)
+                .wrap(Wrap { trim: true })
+                .block(
+                    Block::new()
+                        .title(" Confirm ")
+                        .title_alignment(Alignment::Center)
+                        .bold()
+                        .borders(Borders::ALL)
+                        .border_set(self.border_set(border::THICK)),
+                )
+                .render(popup_area, buf);
+            }
+            AppState::Details { idx } => {
+                let lines = self.details_lines(*idx);
+                let popup_area = popup_area(area, 70, lines.len() as u16 + 2);
+                Clear.render(popup_area, buf);
+                Paragraph::new(lines)
+                    .block(
+                        Block::new()
+                            .title(" Details ")
+                            .title_alignment(Alignment::Center)
+                            .bold()
+                            .borders(Borders::ALL)
+                            .border_set(self.border_set(border::THICK)),
+                    )
+                    .render(popup_area, buf);
+            }
+            AppState::ChoosingPartitionTable { disk, selected, .. } => {
+                let mut lines = vec![
+                    Line::from(format!(
+                        "{} ({})",
+                        disk.name,
+                        self.size_mode.format(disk.size)
+                    )),
+                    Line::default(),
+                ];
+                for (i, table) in PartitionTableType::ALL.iter().enumerate() {
+                    if i == *selected {
+                        lines.push(Line::styled(table.description(), Style::new().reversed()));
+                    } else {
+                        lines.push(Line::from(table.description()));
+                    }
+                }
+                let popup_area = popup_area(area, 50, lines.len() as u16 + 2);
+                Clear.render(popup_area, buf);
+                Paragraph::new(lines)
+                    .alignment(Alignment::Center)
+                    .block(
+                        Block::new()
+                            .title(" Choose partition table type ")
+                            .title_alignment(Alignment::Center)
+                            .bold()
+                            .borders(Borders::ALL)
+                            .border_set(self.border_set(border::THICK)),
+                    )
+                    .render(popup_area, buf);
+            }
+            AppState::ConfirmingPartitionTable {
+                disk, table, input, ..
+            } => {
+                let lines = vec![
+                    Line::styled(
+                        format!(
+                            "ALL DATA ON THE WHOLE DISK {} ({}) WILL BE DESTROYED",
+                            disk.name,
+                            self.size_mode.format(disk.size)
+                        ),
+                        Style::new().red().bold(),
+                    ),
+                    Line::from(format!(
+                        "Every partition on it is removed and replaced by an empty {} partition table",
+                        table.name()
+                    )),
+                    Line::default(),
+                    Line::from(format!("Type {} to confirm:", disk.name)),
+                    Line::from(input.as_str()),
+                ];
+                let popup_area = popup_area(area, 60, lines.len() as u16 + 3);
+                Clear.render(popup_area, buf);
+                Paragraph::new(lines)
+                    .alignment(Alignment::Center)
+                    .wrap(Wrap { trim: true })
+                    .block(
+                        Block::new()
+                            .title(" New partition table ")
+                            .title_alignment(Alignment::Center)
+                            .bold()
+                            .red()
+                            .borders(Borders::ALL)
+                            .border_set(self.border_set(border::THICK)),
+                    )
+                    .render(popup_area, buf);
+            }
+            AppState::ConfirmingWipe { idx, method, input } => {
+                let device = &self.gui_devices[*idx];
+                let lines = vec![
+                    Line::styled(
+                        format!(
+                            "ALL DATA ON {} ({}) WILL BE DESTROYED",
+                            device.info.name,
+                            self.size_mode.format(device.info.size)
+                        ),
+                        Style::new().red().bold(),
+                    ),
+                    Line::from(method.description()),
+                    Line::default(),
+                    Line::from(format!("Type {} to confirm:", device.info.name)),
+                    Line::from(input.as_str()),
+                ];
+                let popup_area = popup_area(area, 60, lines.len() as u16 + 2);
+                Clear.render(popup_area, buf);
+                Paragraph::new(lines)
+                    .alignment(Alignment::Center)
+                    .block(
+                        Block::new()
+                            .title(" Wipe device ")
+                            .title_alignment(Alignment::Center)
+                            .bold()
+                            .red()
+                            .borders(Borders::ALL)
+                            .border_set(self.border_set(border::THICK)),
+                    )
+                    .render(popup_area, buf);
+            }
         }
     }
 }
 
-impl GuiDevice {
-    async fn new(client: &Client, block_device: &BlockDevice) -> Result<Self> {
-        let (path, mount_point) = match block_device.kind {
-            BlockDeviceKind::Filesystem => {
-                let filesystem_proxy = FilesystemProxy::builder(client.conn())
-                    .path(&block_device.path)?
-                    .build()
-                    .await?;
-                let mount_point = match filesystem_proxy.mount_points().await?.first() {
-                    Some(mount_point) => CStr::from_bytes_with_nul(mount_point)?
-                        .to_string_lossy()
-                        .to_string(),
-                    None => String::new(),
-                };
-                (Cow::Borrowed(&block_device.path), mount_point)
-            }
-            BlockDeviceKind::Encrypted => {
-                let encrypted_proxy = EncryptedProxy::builder(client.conn())
-                    .path(&block_device.path)?
-                    .build()
-                    .await?;
-                let cleartext_device = encrypted_proxy.cleartext_device().await?;
-                if cleartext_device.len() > 1 {
-                    let filesystem_proxy = FilesystemProxy::builder(client.conn())
-                        .path(&cleartext_device)?
-                        .build()
-                        .await?;
-                    let mount_point = match filesystem_proxy.mount_points().await?.first() {
-                        Some(mount_point) => CStr::from_bytes_with_nul(mount_point)?
-                            .to_string_lossy()
-                            .to_string(),
-                        None => String::new(),
-                    };
-                    (Cow::Owned(cleartext_device), mount_point)
+impl App {
+    /// Keys valid in the current state and what they do, in two lines. The
+    /// keys of the devices list are the ones bound in the [`Keymap`].
+    fn footer_hints(&self) -> [Vec<(String, &'static str)>; 2] {
+        let fixed = |hints: &[(&str, &'static str)]| -> Vec<(String, &'static str)> {
+            hints
+                .iter()
+                .map(|&(key, action)| (key.to_string(), action))
+                .collect()
+        };
+        match &self.state {
+            AppState::DisksList => {}
+            AppState::ReadingPassphrase { .. }
+            | AppState::Input { .. }
+            | AppState::ConfirmingWipe { .. }
+            | AppState::ConfirmingPartitionTable { .. } => {
+                return [fixed(&[("<Enter>", "Submit"), ("<Esc>", "Cancel")]), vec![]];
+            }
+            AppState::ChoosingMountOptions { .. }
+            | AppState::ChoosingSubvolume { .. }
+            | AppState::ChoosingEraseMethod { .. }
+            | AppState::ChoosingPartitionTable { .. } => {
+                return [
+                    fixed(&[("j/k", "Move"), ("<Enter>", "Select"), ("<Esc>", "Cancel")]),
+                    vec![],
+                ];
+            }
+            AppState::Confirming {
+                action: ConfirmAction::TakeOwnership { recursive, .. },
+                ..
+            } => {
+                let recursive = if *recursive {
+                    "Only the top directory"
                 } else {
-                    (Cow::Borrowed(&block_device.path), String::new())
-                }
+                    "Recursive"
+                };
+                return [
+                    fixed(&[("y", "Yes"), ("n", "No"), ("r", recursive)]),
+                    vec![],
+                ];
             }
-        };
-        let proxy = BlockProxy::builder(client.conn())
-            .path(path.as_ref())?
-            .build()
-            .await?;
-        let name = Device::get_name(&proxy).await?;
-        let label = Device::get_label(&proxy).await?;
-        let size = Device::get_size(&proxy).await?;
-        let state = Device::get_state(client, block_device).await?;
-        Ok(Self {
-            info: GuiDeviceInfo {
-                name,
-                label,
-                size,
-                mount_point,
-            },
+            AppState::Confirming { .. } => return [fixed(&[("y", "Yes"), ("n", "No")]), vec![]],
+            AppState::Details { .. } => {
+                return [fixed(&[("y", "Copy path"), ("<Esc>", "Close")]), vec![]];
+            }
+            AppState::Error { .. } | AppState::Help { .. } => {
+                return [fixed(&[("j/k", "Scroll"), ("<Esc>", "Close")]), vec![]];
+            }
+        }
+
+        // actions without a key aren't shown
+        let hint = |action, label| self.keymap.key(action).map(|key| (key.to_string(), label));
+        let state = self
+            .gui_devices
+            .get(self.selected_device_index)
+            .map(|device| device.state);
+        let mut first = vec![];
+        match state {
+            Some(DeviceState::Locked) => {
+                first.extend(hint(Action::Mount, "Unlock and mount"));
+                first.extend(hint(Action::Unlock, "Unlock only"));
+            }
+            Some(DeviceState::UnmountedUnlocked) => {
+                first.extend(hint(Action::Mount, "Mount"));
+                first.extend(hint(Action::Unmount, "Lock"));
+            }
+            Some(DeviceState::Unmounted) => first.extend(hint(Action::Mount, "Mount")),
+            Some(DeviceState::Mounted)
+                if self.gui_devices[self.selected_device_index]
+                    .info
+                    .mounted_externally() =>
+            {
+                first.extend(hint(Action::Unmount, "Unmount (needs admin)"))
+            }
+            Some(DeviceState::Mounted) => first.extend(hint(Action::Unmount, "Unmount")),
+            Some(DeviceState::Failed) => first.extend(hint(Action::Reload, "Retry")),
+            Some(DeviceState::NoMedia | DeviceState::Member) | None => {}
+        }
+        if matches!(
+            state,
+            Some(DeviceState::Locked | DeviceState::UnmountedUnlocked | DeviceState::Unmounted)
+        ) {
+            first.extend(hint(Action::MountWithOptions, "Mount with options"));
+            first.extend(hint(Action::MountAs, "Mount as type"));
+        }
+        if state.is_some() {
+            first.extend(hint(Action::Eject, "Eject"));
+        }
+        first.extend(
+            [
+                hint(Action::EjectAll, "Eject all"),
+                hint(Action::Refresh, "Refresh"),
+                hint(Action::Details, "Details"),
+                hint(Action::SizeUnits, "Size units"),
+                hint(Action::Sort, "Sort"),
+                hint(Action::MountableOnly, "Mountable only"),
+            ]
+            .into_iter()
+            .flatten(),
+        );
+
+        let mut second = vec![];
+        // the filesystem of encrypted devices can only be reached when unlocked
+        let has_filesystem = matches!(
             state,
-        })
+            Some(DeviceState::Mounted | DeviceState::UnmountedUnlocked | DeviceState::Unmounted)
+        );
+        if has_filesystem {
+            second.extend(
+                [
+                    hint(Action::Label, "Label"),
+                    hint(Action::Resize, "Resize"),
+                    hint(Action::Check, "Check"),
+                    hint(Action::Repair, "Repair"),
+                ]
+                .into_iter()
+                .flatten(),
+            );
+        }
+        if state == Some(DeviceState::Mounted) {
+            second.extend(hint(Action::BindMount, "Bind mount"));
+        } else if has_filesystem {
+            second.extend(hint(Action::MountAt, "Mount at"));
+        }
+        if state.is_some() {
+            second.extend(hint(Action::Wipe, "Wipe"));
+            second.extend(hint(Action::PartitionTable, "Partition table"));
+        }
+        second.extend(hint(
+            Action::Activate,
+            match self.config.enter_action {
+                EnterAction::MountAndExit if self.config.stay => {
+                    "Mount, printing mount point on quit"
+                }
+                EnterAction::MountAndExit => "Mount and exit printing mount point",
+                EnterAction::Mount => "Mount",
+                EnterAction::Details => "Details",
+                EnterAction::Open => "Mount and open",
+            },
+        ));
+        second.extend(hint(Action::Help, "Help"));
+        second.extend(hint(Action::Quit, "Quit"));
+        [first, second]
     }
 }
 
-impl Display for DeviceState {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s = match self {
-            DeviceState::Locked => "Locked",
-            DeviceState::UnmountedUnlocked => "Unlocked",
-            DeviceState::Mounted => "Mounted",
-            DeviceState::Unmounted => "Unmounted",
-        };
-        write!(f, "{}", s)
+/// Borders drawn with ASCII only, see [`Config::ascii`].
+const ASCII_BORDER: border::Set = border::Set {
+    top_left: "+",
+    top_right: "+",
+    bottom_left: "+",
+    bottom_right: "+",
+    vertical_left: "|",
+    vertical_right: "|",
+    horizontal_top: "-",
+    horizontal_bottom: "-",
+};
+
+impl Column {
+    fn title(&self) -> &'static str {
+        match self {
+            Column::Name => "Name",
+            Column::Label => "Label",
+            Column::MountPoint => "Mount Point",
+            Column::Size => "Size",
+            Column::Usage => "Usage",
+            Column::Used => "Used",
+            Column::Fstype => "Type",
+            Column::Uuid => "UUID",
+            Column::Bus => "Bus",
+            Column::Status => "Status",
+            Column::Auto => "Auto",
+            Column::LastMounted => "Last mounted",
+        }
+    }
+
+    /// Whether the column is kept in compact mode.
+    fn is_essential(&self) -> bool {
+        matches!(
+            self,
+            Column::Name | Column::MountPoint | Column::Size | Column::Status
+        )
+    }
+
+    fn width(&self) -> Constraint {
+        match self {
+            Column::Name | Column::Label | Column::MountPoint => Constraint::Fill(1),
+            Column::Size | Column::Used => Constraint::Max(16),
+            Column::Usage => Constraint::Max(22),
+            Column::Fstype => Constraint::Max(12),
+            Column::Uuid => Constraint::Max(36),
+            Column::Bus => Constraint::Max(8),
+            Column::Status => Constraint::Max(14),
+            Column::Auto => Constraint::Max(4),
+            Column::LastMounted => Constraint::Max(12),
+        }
+    }
+}
+
+/// Renders `usage` as `[####----]  50%` filling `width` columns, colored by
+/// how full the filesystem is.
+fn usage_bar(usage: Usage, width: u16) -> Line<'static> {
+    let ratio = usage.ratio();
+    let style = match ratio {
+        r if r >= 0.9 => Style::new().red(),
+        r if r >= 0.75 => Style::new().yellow(),
+        _ => Style::new().green(),
+    };
+    let percent = format!("{:>4.0}%", ratio * 100.0);
+    let inner = (width as usize).saturating_sub(percent.len() + 2);
+    if inner == 0 {
+        return Line::styled(percent.trim_start().to_string(), style);
     }
+    let filled = ((ratio * inner as f64).round() as usize).min(inner);
+    Line::styled(
+        format!(
+            "[{}{}]{percent}",
+            "#".repeat(filled),
+            "-".repeat(inner - filled)
+        ),
+        style,
+    )
+}
+
+/// Whether the current directory is at or below `mount_point`.
+fn is_cwd_inside(mount_point: &str) -> bool {
+    if mount_point.is_empty() {
+        return false;
+    }
+    let cwd = std::env::current_dir().ok();
+    let pwd = std::env::var_os("PWD").map(PathBuf::from);
+    [cwd, pwd]
+        .into_iter()
+        .flatten()
+        .any(|dir| dir.starts_with(mount_point))
+}
+
+fn popup_area(area: Rect, width: u16, height: u16) -> Rect {
+    let popup_layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(width),
+            Constraint::Fill(1),
+        ])
+        .split(area);
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(height),
+            Constraint::Fill(2),
+        ])
+        .split(popup_layout[1]);
+    popup_layout[1]
+}
+
+/// The key of `event` as bound in the [`Keymap`]. Shift isn't part of it, as
+/// it is already part of the character, e.g. `G`.
+fn key_of(event: KeyEvent) -> Option<Key> {
+    let code = match event.code {
+        KeyCode::Char(c) => keymap::KeyCode::Char(c),
+        KeyCode::Enter => keymap::KeyCode::Enter,
+        KeyCode::Esc => keymap::KeyCode::Esc,
+        KeyCode::Tab => keymap::KeyCode::Tab,
+        KeyCode::Backspace => keymap::KeyCode::Backspace,
+        KeyCode::Delete => keymap::KeyCode::Delete,
+        KeyCode::Up => keymap::KeyCode::Up,
+        KeyCode::Down => keymap::KeyCode::Down,
+        KeyCode::Left => keymap::KeyCode::Left,
+        KeyCode::Right => keymap::KeyCode::Right,
+        KeyCode::Home => keymap::KeyCode::Home,
+        KeyCode::End => keymap::KeyCode::End,
+        KeyCode::PageUp => keymap::KeyCode::PageUp,
+        KeyCode::PageDown => keymap::KeyCode::PageDown,
+        KeyCode::F(n) => keymap::KeyCode::F(n),
+        _ => return None,
+    };
+    Some(Key {
+        code,
+        ctrl: event.modifiers.contains(KeyModifiers::CONTROL),
+    })
 }
@@ -0,0 +1,89 @@
+//! Bind mounts and mounts at a chosen directory, which udisks2 doesn't
+//! support, done through `pkexec mount`.
+
+use std::{
+    fs,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use color_eyre::{eyre::eyre, Result};
+use rustix::fs::{access, Access};
+use tokio::process::Command;
+
+/// Whether pkexec may start its own text authentication agent on the
+/// terminal when no polkit agent is running in the session.
+static INTERNAL_AGENT: AtomicBool = AtomicBool::new(true);
+
+/// Makes pkexec fail instead of prompting on the terminal when there is no
+/// polkit agent to authenticate with.
+pub fn disable_internal_agent() {
+    INTERNAL_AGENT.store(false, Ordering::Relaxed);
+}
+
+#[derive(Debug, Clone)]
+pub struct BindMount {
+    /// Mount point of the filesystem being bind mounted.
+    pub source: String,
+    pub target: String,
+}
+
+impl BindMount {
+    pub async fn mount(source: String, target: String) -> Result<Self> {
+        privileged(&["mount", "--bind", "--", &source, &target]).await?;
+        Ok(Self { source, target })
+    }
+
+    pub async fn unmount(&self) -> Result<()> {
+        privileged(&["umount", "--", &self.target]).await
+    }
+}
+
+/// Mounts `device` at `target`, which should be checked with
+/// [`check_target`] first. Like udisks2 does, setuid binaries and device
+/// nodes on it are ignored, since it's mounted as root.
+pub async fn mount_at(device: &Path, target: &str) -> Result<()> {
+    let device = device
+        .to_str()
+        .ok_or_else(|| eyre!("{} is not valid UTF-8", device.display()))?;
+    privileged(&["mount", "-o", "nosuid,nodev", "--", device, target]).await
+}
+
+/// Checks that `target` is an existing, empty and writable directory.
+pub fn check_target(target: &Path) -> Result<(), String> {
+    let display = target.display();
+    let mut entries = match fs::read_dir(target) {
+        Ok(entries) => entries,
+        Err(_) if !target.exists() => return Err(format!("{display} doesn't exist")),
+        Err(_) if !target.is_dir() => return Err(format!("{display} is not a directory")),
+        Err(err) => return Err(format!("can't read {display}: {err}")),
+    };
+    if entries.next().is_some() {
+        return Err(format!("{display} is not empty"));
+    }
+    if access(target, Access::WRITE_OK).is_err() {
+        return Err(format!("{display} is not writable"));
+    }
+    Ok(())
+}
+
+async fn privileged(args: &[&str]) -> Result<()> {
+    let mut command = Command::new("pkexec");
+    if !INTERNAL_AGENT.load(Ordering::Relaxed) {
+        command.arg("--disable-internal-agent");
+    }
+    let output = command
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(eyre!(
+            "{} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
@@ -0,0 +1,50 @@
+//! Finding btrfs subvolumes without root, which `btrfs subvolume list`
+//! needs, by their root directories always having inode 256.
+
+use std::{
+    fs,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+/// Inode number of the root directory of every subvolume.
+const SUBVOLUME_ROOT_INODE: u64 = 256;
+/// Subvolumes nested deeper than this are not looked for.
+const MAX_DEPTH: usize = 4;
+/// Listing stops after finding this many.
+const MAX_SUBVOLUMES: usize = 200;
+
+/// Paths relative to `top_level`, the mount point of the top level
+/// subvolume, of the subvolumes found below it, in the form `subvol=`
+/// takes.
+pub fn subvolumes(top_level: &Path) -> Vec<String> {
+    let mut subvolumes = vec![];
+    let mut dirs = vec![(PathBuf::new(), 0)];
+    while let Some((dir, depth)) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(top_level.join(&dir)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            // file_type doesn't follow symlinks
+            if !entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                continue;
+            }
+            let path = dir.join(entry.file_name());
+            if entry
+                .metadata()
+                .is_ok_and(|metadata| metadata.ino() == SUBVOLUME_ROOT_INODE)
+            {
+                subvolumes.push(path.to_string_lossy().into_owned());
+                if subvolumes.len() >= MAX_SUBVOLUMES {
+                    subvolumes.sort();
+                    return subvolumes;
+                }
+            }
+            if depth + 1 < MAX_DEPTH {
+                dirs.push((path, depth + 1));
+            }
+        }
+    }
+    subvolumes.sort();
+    subvolumes
+}
@@ -0,0 +1,614 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::{ArgAction, Parser, ValueEnum};
+use color_eyre::{
+    eyre::{bail, eyre, Context},
+    Report, Result,
+};
+use futures_util::StreamExt;
+use log::{warn, LevelFilter};
+use rustix::termios::{tcgetattr, tcsetattr, LocalModes, OptionalActions};
+use secstr::SecStr;
+use simplelog::{ConfigBuilder, WriteLogger};
+use tokio::runtime::Runtime;
+use udiskstui::{
+    config::Config,
+    device::{list_devices, Device, DeviceMessage, DeviceState, GuiDevice},
+    matcher::{matching_all, matching_devices},
+    mount_options::MountOptions,
+    udisks2::UdisksError,
+    Client,
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about,
+    after_help = "Exit status: 0 on success, 77 when not authorized, 75 when the device is \
+                  busy, 69 when there is no such device or the operation isn't supported and 1 \
+                  for other errors\n\n\
+                  Environment: UDISKSTUI_BUS_ADDRESS connects to the bus at that address \
+                  instead of the system or session bus, unless --session-bus or the \
+                  session_bus config key is set. UDISKSTUI_SERVICE talks to that service \
+                  name instead of org.freedesktop.UDisks2"
+)]
+pub struct Args {
+    /// Mount the device whose name, label or UUID fuzzy matches QUERY and
+    /// print its mount point, without starting the TUI
+    #[arg(long, value_name = "QUERY")]
+    pub mount: Option<String>,
+
+    /// When several devices match --mount or --toggle, pick the best match
+    /// instead of failing
+    #[arg(long)]
+    pub first: bool,
+
+    /// Mount the device best matching QUERY, e.g. /dev/sdb1, if it isn't
+    /// mounted, or unmount and lock it if it is, and print its new state,
+    /// without starting the TUI. The passphrase of locked devices is asked
+    /// for on the terminal
+    #[arg(long, value_name = "QUERY", conflicts_with_all = ["mount", "status", "watch"])]
+    pub toggle: Option<String>,
+
+    /// Mount every device whose name, label or UUID matches PATTERN, a glob
+    /// like `backup-*` or else a fuzzy query, and print their mount points,
+    /// one per line, without starting the TUI
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        conflicts_with_all = ["mount", "toggle", "status", "watch"]
+    )]
+    pub mount_all_matching: Option<String>,
+
+    /// With --mount-all-matching, unlock encrypted devices with the
+    /// passphrases in PATH, one `UUID passphrase` line per device. Locked
+    /// devices without a line are skipped
+    #[arg(long, value_name = "PATH", requires = "mount_all_matching")]
+    pub passphrase_file: Option<PathBuf>,
+
+    /// With --toggle, fail instead of asking for a passphrase
+    #[arg(long, requires = "toggle")]
+    pub non_interactive: bool,
+
+    /// Don't exit after mounting with Enter, the mount point is still
+    /// printed when quitting
+    #[arg(long)]
+    pub stay: bool,
+
+    /// Print a summary like `3 mounted, 1 locked` and exit. The counts are
+    /// always in the order mounted, unlocked, locked, unmounted, and zeros are
+    /// left out
+    #[arg(long, conflicts_with_all = ["mount", "watch"])]
+    pub status: bool,
+
+    /// With --status, print the name, state and mount point of the device
+    /// best matching QUERY instead
+    #[arg(long, value_name = "QUERY", requires = "status")]
+    pub device: Option<String>,
+
+    /// Print a line for every device that is added (+), removed (-) or
+    /// changes state (~) until interrupted, without starting the TUI
+    #[arg(long, conflicts_with = "mount")]
+    pub watch: bool,
+
+    /// With --watch, list the devices every SECS seconds when udisks2's
+    /// signals can't be subscribed to, instead of every 30 seconds
+    #[arg(long, value_name = "SECS", requires = "watch")]
+    pub poll_interval: Option<u64>,
+
+    /// Connect to udisks2 on the session bus instead of the system bus, which
+    /// is otherwise only tried when udisks2 isn't on the system bus
+    #[arg(long)]
+    pub session_bus: bool,
+
+    /// Don't use colors
+    #[arg(long)]
+    pub no_color: bool,
+
+    /// Fit in small terminals, e.g. a floating one of 60x20, by showing less
+    #[arg(long)]
+    pub compact: bool,
+
+    /// Draw borders and symbols with ASCII only, for terminals without
+    /// Unicode support
+    #[arg(long)]
+    pub ascii: bool,
+
+    /// Write every status message as a line to PATH, which can be a named
+    /// pipe, e.g. for a screen reader
+    #[arg(long, value_name = "PATH")]
+    pub announce: Option<PathBuf>,
+
+    /// Exit after SECS seconds without a key being pressed, not counting the
+    /// time spent typing a passphrase
+    #[arg(long, value_name = "SECS")]
+    pub idle_timeout: Option<u64>,
+
+    /// Don't let pkexec prompt for a password on the terminal when no polkit
+    /// agent is running, which is also the case when stdin isn't a terminal
+    #[arg(long)]
+    pub no_agent: bool,
+
+    /// Append a log of the operations performed to PATH
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Print a `udm` function for SHELL that starts the TUI and changes to
+    /// the mount point of the device mounted with Enter, to be added to the
+    /// shell's startup file, e.g. `eval "$(udiskstui --shell-init bash)"`
+    #[arg(long, value_name = "SHELL", exclusive = true)]
+    pub shell_init: Option<Shell>,
+
+    /// Print the keys bound to each action, with the ones set in the config,
+    /// as a `[keys]` config table
+    #[arg(long, exclusive = true)]
+    pub print_keymap: bool,
+
+    /// Print only the result: the mount point with --mount and --toggle, or
+    /// nothing when --toggle unmounts. Errors are printed to stderr as a
+    /// single line, and -v logs only go to --log-file
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Log more details, -v logs D-Bus calls and tasks, -vv everything.
+    /// Without --log-file, logs go to stderr when not
+    /// starting the TUI
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Prints the `udm` function for `shell`, which passes its arguments on and
+/// changes to the mount point udiskstui prints when exiting after a mount.
+pub fn shell_init(shell: Shell) {
+    let bin = env!("CARGO_PKG_NAME");
+    match shell {
+        Shell::Bash | Shell::Zsh => print!(
+            r#"# Mount a device with Enter in {bin} and change to its mount point
+udm() {{
+    local dir
+    dir="$(command {bin} "$@")" || return
+    [ -n "$dir" ] && cd -- "$dir"
+}}
+"#
+        ),
+        Shell::Fish => print!(
+            r#"# Mount a device with Enter in {bin} and change to its mount point
+function udm --wraps {bin}
+    set -l dir (command {bin} $argv); or return
+    test -n "$dir"; and cd -- $dir
+end
+"#
+        ),
+    }
+}
+
+/// Sets up logging to `--log-file`, or to stderr for the commands that don't
+/// start the TUI, since anything written to the terminal would corrupt it.
+pub fn init_logging(args: &Args) -> Result<()> {
+    let level = match args.verbose {
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let config = ConfigBuilder::new()
+        .add_filter_allow_str("udiskstui")
+        .set_time_format_rfc3339()
+        .build();
+    if let Some(path) = &args.log_file {
+        let file = File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .wrap_err_with(|| format!("failed to open log file {}", path.display()))?;
+        WriteLogger::init(level, config, file)?;
+    } else if args.verbose > 0
+        && !args.quiet
+        && (args.mount.is_some()
+            || args.mount_all_matching.is_some()
+            || args.toggle.is_some()
+            || args.watch
+            || args.status)
+    {
+        WriteLogger::init(level, config, std::io::stderr())?;
+    }
+    Ok(())
+}
+
+pub fn mount(query: &str, first: bool, config: &Config, session_bus: bool) -> Result<()> {
+    let runtime = Runtime::new()?;
+    runtime.block_on(async {
+        let client = Client::connect_to(session_bus).await?;
+        let (devices, gui_devices) = all_devices(&client, config).await?;
+        let idx = find_device(query, first, &gui_devices)?;
+
+        let mut options = MountOptions::default();
+        config.apply_mount_options(&mut options, &gui_devices[idx].info.uuid);
+        match devices[idx].mount(idx, None, &options).await? {
+            DeviceMessage::Mounted(_, mount_point)
+            | DeviceMessage::AlreadyMounted(_, mount_point)
+            | DeviceMessage::UnlockedAndMounted(_, mount_point, _)
+            | DeviceMessage::MountedUnlocked(_, mount_point, _) => println!("{mount_point}"),
+            DeviceMessage::PassphraseRequired(..) => {
+                bail!("{} is locked, unlock it first", gui_devices[idx].info.name)
+            }
+            _ => {}
+        }
+        Ok(())
+    })
+}
+
+/// Mounts every device matching `pattern` and prints their mount points,
+/// unlocking encrypted ones with the passphrases in `passphrase_file`. The
+/// devices that fail don't stop the others, but make the whole command fail.
+pub fn mount_all_matching(
+    pattern: &str,
+    passphrase_file: Option<&Path>,
+    config: &Config,
+    session_bus: bool,
+) -> Result<()> {
+    let mut passphrases = match passphrase_file {
+        Some(path) => read_passphrase_file(path)?,
+        None => HashMap::new(),
+    };
+    let runtime = Runtime::new()?;
+    runtime.block_on(async {
+        let client = Client::connect_to(session_bus).await?;
+        let (devices, gui_devices) = all_devices(&client, config).await?;
+        let matches: Vec<usize> = matching_all(pattern, gui_devices.iter().map(|d| &d.info))
+            .into_iter()
+            .filter(|&idx| {
+                !matches!(
+                    gui_devices[idx].state,
+                    DeviceState::NoMedia | DeviceState::Member | DeviceState::Failed
+                )
+            })
+            .collect();
+        if matches.is_empty() {
+            return Err(no_match(pattern));
+        }
+
+        let mut failed = 0;
+        for &idx in &matches {
+            let info = &gui_devices[idx].info;
+            let mut options = MountOptions::default();
+            config.apply_mount_options(&mut options, &info.uuid);
+            let passphrase = passphrases.remove(&info.uuid);
+            let result = match devices[idx].mount(idx, passphrase, &options).await {
+                Ok(DeviceMessage::PassphraseRequired(..)) => {
+                    Err(eyre!("locked, and no passphrase for UUID {}", info.uuid))
+                }
+                result => result,
+            };
+            match result {
+                Ok(
+                    DeviceMessage::Mounted(_, mount_point)
+                    | DeviceMessage::AlreadyMounted(_, mount_point)
+                    | DeviceMessage::UnlockedAndMounted(_, mount_point, _)
+                    | DeviceMessage::MountedUnlocked(_, mount_point, _),
+                ) => println!("{mount_point}"),
+                Ok(_) => {}
+                Err(err) => {
+                    eprintln!("{}: {err:#}", info.name);
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            bail!("failed to mount {failed} of {} devices", matches.len());
+        }
+        Ok(())
+    })
+}
+
+/// Reads `UUID passphrase` lines, where the passphrase is the rest of the
+/// line after the whitespace following the UUID. Empty lines and lines
+/// starting with `#` are skipped.
+fn read_passphrase_file(path: &Path) -> Result<HashMap<String, SecStr>> {
+    let file = File::open(path)
+        .wrap_err_with(|| format!("failed to open passphrase file {}", path.display()))?;
+    if file.metadata()?.permissions().mode() & 0o077 != 0 {
+        warn!(
+            "{} can be read by other users, it should only be readable by its owner",
+            path.display()
+        );
+    }
+    let mut passphrases = HashMap::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((uuid, passphrase)) = line.split_once(char::is_whitespace) else {
+            bail!(
+                "{}:{}: expected `UUID passphrase`",
+                path.display(),
+                number + 1
+            );
+        };
+        let passphrase = passphrase.trim_start().trim_end_matches(['\r', '\n']);
+        passphrases.insert(
+            uuid.to_string(),
+            SecStr::new(passphrase.as_bytes().to_vec()),
+        );
+    }
+    Ok(passphrases)
+}
+
+/// Mounts the device matching `query` if it isn't mounted, or unmounts and
+/// locks it if it is, and prints its new state.
+pub fn toggle(
+    query: &str,
+    first: bool,
+    interactive: bool,
+    quiet: bool,
+    config: &Config,
+    session_bus: bool,
+) -> Result<()> {
+    let runtime = Runtime::new()?;
+    runtime.block_on(async {
+        let client = Client::connect_to(session_bus).await?;
+        let (devices, gui_devices) = all_devices(&client, config).await?;
+        let idx = find_device(query, first, &gui_devices)?;
+        let device = &devices[idx];
+        let name = &gui_devices[idx].info.name;
+
+        if gui_devices[idx].state == DeviceState::Mounted {
+            device.unmount(idx).await?;
+        } else {
+            let mut options = MountOptions::default();
+            config.apply_mount_options(&mut options, &gui_devices[idx].info.uuid);
+            if let DeviceMessage::PassphraseRequired(..) = device.mount(idx, None, &options).await?
+            {
+                if !interactive {
+                    bail!("{name} is locked, and --non-interactive doesn't allow asking for the passphrase");
+                }
+                let passphrase = read_passphrase(&format!("Passphrase for {name}: "))?;
+                device.mount(idx, Some(passphrase), &options).await?;
+            }
+        }
+
+        let gui_device = GuiDevice::new(&client, device.block_device()).await?;
+        if !quiet {
+            println!(
+                "{name} {}",
+                describe(gui_device.state, &gui_device.info.mount_point)
+            );
+        } else if !gui_device.info.mount_point.is_empty() {
+            println!("{}", gui_device.info.mount_point);
+        }
+        Ok(())
+    })
+}
+
+/// Index of the device matching `query`, failing if there is none, or if
+/// there are several and `first` isn't set.
+fn find_device(query: &str, first: bool, gui_devices: &[GuiDevice]) -> Result<usize> {
+    let matches = matching_devices(query, gui_devices.iter().map(|d| &d.info));
+    match matches[..] {
+        [] => Err(no_match(query)),
+        [idx] => Ok(idx),
+        [idx, ..] if first => Ok(idx),
+        _ => {
+            let candidates: Vec<String> = matches
+                .iter()
+                .map(|&idx| {
+                    let info = &gui_devices[idx].info;
+                    if info.label.is_empty() {
+                        format!("  {}", info.name)
+                    } else {
+                        format!("  {} ({})", info.name, info.label)
+                    }
+                })
+                .collect();
+            bail!(
+                "{query:?} matches several devices, use --first to pick the best one:\n{}",
+                candidates.join("\n")
+            )
+        }
+    }
+}
+
+fn no_match(query: &str) -> Report {
+    Report::new(UdisksError::NoSuchDevice).wrap_err(format!("no device matches {query:?}"))
+}
+
+/// Reads a passphrase from the controlling terminal without echoing it, so it
+/// works even when stdin and stdout are redirected.
+fn read_passphrase(prompt: &str) -> Result<SecStr> {
+    let mut tty = File::options()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .wrap_err("no terminal to ask for the passphrase on")?;
+    write!(tty, "{prompt}")?;
+    let termios = tcgetattr(&tty)?;
+    let mut no_echo = termios.clone();
+    no_echo.local_modes.remove(LocalModes::ECHO);
+    tcsetattr(&tty, OptionalActions::Now, &no_echo)?;
+    let mut line = String::new();
+    let read = BufReader::new(&tty).read_line(&mut line);
+    tcsetattr(&tty, OptionalActions::Now, &termios)?;
+    writeln!(tty)?;
+    read?;
+    while line.ends_with(['\r', '\n']) {
+        line.pop();
+    }
+    Ok(SecStr::new(line.into_bytes()))
+}
+
+/// The devices of the kinds listed by `device_kinds` in the config, like in
+/// the TUI.
+async fn listed_devices(client: &Client, config: &Config) -> Result<Vec<Device>> {
+    let mut devices = list_devices(client).await?;
+    devices.retain(|device| config.device_kinds.contains(&device.block_device().kind));
+    Ok(devices)
+}
+
+async fn all_devices(client: &Client, config: &Config) -> Result<(Vec<Device>, Vec<GuiDevice>)> {
+    let devices = listed_devices(client, config).await?;
+    let mut gui_devices = Vec::with_capacity(devices.len());
+    for device in &devices {
+        gui_devices.push(GuiDevice::new(client, device.block_device()).await?);
+    }
+    Ok((devices, gui_devices))
+}
+
+pub fn status(device: Option<&str>, config: &Config, session_bus: bool) -> Result<()> {
+    let runtime = Runtime::new()?;
+    runtime.block_on(async {
+        let client = Client::connect_to(session_bus).await?;
+        let (_, gui_devices) = all_devices(&client, config).await?;
+
+        if let Some(query) = device {
+            let Some(&idx) = matching_devices(query, gui_devices.iter().map(|d| &d.info)).first()
+            else {
+                return Err(no_match(query));
+            };
+            let device = &gui_devices[idx];
+            println!(
+                "{} {}",
+                device.info.name,
+                describe(device.state, &device.info.mount_point)
+            );
+            return Ok(());
+        }
+
+        let counts: Vec<String> = [
+            DeviceState::Mounted,
+            DeviceState::UnmountedUnlocked,
+            DeviceState::Locked,
+            DeviceState::Unmounted,
+        ]
+        .into_iter()
+        .filter_map(|state| {
+            let count = gui_devices.iter().filter(|d| d.state == state).count();
+            (count > 0).then(|| format!("{count} {}", state.to_string().to_lowercase()))
+        })
+        .collect();
+        if counts.is_empty() {
+            println!("no devices");
+        } else {
+            println!("{}", counts.join(", "));
+        }
+        Ok(())
+    })
+}
+
+/// How long to wait for more signals before listing the devices again, since
+/// a single change makes udisks2 emit a burst of them.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// How often the devices are listed when the signals can't be subscribed to.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+pub fn watch(
+    poll_interval: Option<u64>,
+    quiet: bool,
+    config: &Config,
+    session_bus: bool,
+) -> Result<()> {
+    let runtime = Runtime::new()?;
+    runtime.block_on(async {
+        let client = Client::connect_to(session_bus).await?;
+        // polling at most every second, so a tiny interval can't flood udisks2
+        let poll_interval = poll_interval
+            .map(|secs| Duration::from_secs(secs.max(1)))
+            .unwrap_or(DEFAULT_POLL_INTERVAL);
+        let mut changes = match client.changes().await {
+            Ok(changes) => Some(changes),
+            Err(err) => {
+                warn!("failed to subscribe to udisks2 signals: {err:#}");
+                if !quiet {
+                    eprintln!(
+                        "can't be notified of changes, listing the devices every {}s instead",
+                        poll_interval.as_secs()
+                    );
+                }
+                None
+            }
+        };
+        let mut known = BTreeMap::new();
+        loop {
+            let current = device_states(&client, config).await?;
+            for (name, (state, mount_point)) in &current {
+                match known.get(name) {
+                    None => println!("+ {name} {}", describe(*state, mount_point)),
+                    Some(old) if old != &(*state, mount_point.clone()) => {
+                        println!("~ {name} {}", describe(*state, mount_point))
+                    }
+                    Some(_) => {}
+                }
+            }
+            for name in known.keys().filter(|name| !current.contains_key(*name)) {
+                println!("- {name}");
+            }
+            known = current;
+
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => return Ok(()),
+                changed = next_change(changes.as_mut(), poll_interval) => {
+                    if !changed {
+                        bail!("lost the connection to udisks2");
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Waits for the next burst of signals, or for `poll_interval` to pass
+/// without `changes`. False if the signals stopped because the connection
+/// was lost.
+async fn next_change(changes: Option<&mut zbus::MessageStream>, poll_interval: Duration) -> bool {
+    let Some(changes) = changes else {
+        tokio::time::sleep(poll_interval).await;
+        return true;
+    };
+    if changes.next().await.is_none() {
+        return false;
+    }
+    while let Ok(Some(_)) = tokio::time::timeout(WATCH_DEBOUNCE, changes.next()).await {}
+    true
+}
+
+/// State and mount point of every device by name. Devices that can't be
+/// queried, e.g. because they were removed meanwhile, are left out.
+async fn device_states(
+    client: &Client,
+    config: &Config,
+) -> Result<BTreeMap<String, (DeviceState, String)>> {
+    let mut states = BTreeMap::new();
+    for device in listed_devices(client, config).await? {
+        if let Ok(gui_device) = GuiDevice::new(client, device.block_device()).await {
+            states.insert(
+                gui_device.info.name,
+                (gui_device.state, gui_device.info.mount_point),
+            );
+        }
+    }
+    Ok(states)
+}
+
+fn describe(state: DeviceState, mount_point: &str) -> String {
+    let state = state.to_string().to_lowercase();
+    if mount_point.is_empty() {
+        state
+    } else {
+        format!("{state} {mount_point}")
+    }
+}
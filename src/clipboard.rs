@@ -0,0 +1,14 @@
+//! Copying to the clipboard with the OSC 52 escape sequence, which most
+//! terminals support, also over SSH.
+
+use std::io::{self, stderr, Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// Copies `bytes` to the clipboard, as is, so paths that aren't valid UTF-8
+/// are copied intact.
+pub fn copy(bytes: &[u8]) -> io::Result<()> {
+    let mut stderr = stderr();
+    write!(stderr, "\x1b]52;c;{}\x07", STANDARD.encode(bytes))?;
+    stderr.flush()
+}
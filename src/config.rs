@@ -0,0 +1,304 @@
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use log::warn;
+use serde::Deserialize;
+
+use crate::{
+    device::DeviceState,
+    keymap::{Action, Key},
+    mount_options::{MountOptions, Owner},
+    udisks2::BlockDeviceKind,
+};
+
+/// Written to the config path on the first run, documenting every setting.
+const DEFAULT_CONFIG: &str = include_str!("default_config.toml");
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Named mount option presets, e.g. `fast = "noatime,nodiratime"`.
+    pub mount_options: BTreeMap<String, String>,
+    pub enter_action: EnterAction,
+    /// Never exit after mounting with Enter.
+    pub stay: bool,
+    /// Ask before mounting and exiting with Enter.
+    pub confirm_exit: bool,
+    /// Pressing m twice within this many milliseconds mounts and exits like
+    /// Enter, without asking even with `confirm_exit`. 0 turns it off.
+    pub double_tap_ms: u64,
+    /// Filesystem types to mount devices as by UUID, for when udisks2 picks
+    /// the wrong driver, e.g. `"0123-4567" = "ntfs3"`.
+    pub fstypes: BTreeMap<String, String>,
+    /// Mount options added for every filesystem of a type, with `{uid}`,
+    /// `{gid}` and `{user}` replaced, e.g. `vfat = "flush"`.
+    pub fstype_options: BTreeMap<String, String>,
+    /// Mount vfat, exfat and ntfs filesystems, which have no file owners,
+    /// with `uid` and `gid` options making the current user own the files.
+    pub own_files: bool,
+    /// `umask` option passed along with `own_files`, e.g. `"077"`.
+    pub umask: Option<String>,
+    /// Names to show for each device, the first non-empty one is used.
+    pub name_order: Vec<NameSource>,
+    /// Show the full device path, e.g. `/dev/mapper/luks-...`, instead of
+    /// its last component at startup, n switches between them.
+    pub long_names: bool,
+    /// Don't use colors, also enabled by a non-empty `NO_COLOR`.
+    pub no_color: bool,
+    /// File or named pipe every status message is written to as a line, e.g.
+    /// for a screen reader to pick up.
+    pub announce: Option<PathBuf>,
+    /// Ring the terminal bell when a mount, unmount or eject finishes, twice
+    /// if an operation failed.
+    pub bell: bool,
+    /// Shell command run when a mount, unmount or eject finishes, e.g.
+    /// `paplay /usr/share/sounds/freedesktop/stereo/complete.oga`.
+    pub success_command: Option<String>,
+    /// Shell command run when an operation fails.
+    pub error_command: Option<String>,
+    /// Draw borders and symbols with ASCII only, for terminals without
+    /// Unicode support. Defaults to whether the locale isn't UTF-8 or the
+    /// terminal is a basic one like `vt100`.
+    pub ascii: Option<bool>,
+    /// Columns of the devices table, in order.
+    pub columns: Vec<Column>,
+    /// Show the size of the drive after that of partitions at startup, e.g.
+    /// `32 GB / 256 GB drive`, z toggles it.
+    pub drive_size: bool,
+    /// Fit in small terminals, with a single status line, one line of key
+    /// hints, no table header and only the name, mount point, size and
+    /// status columns.
+    pub compact: bool,
+    /// How the devices are sorted at startup, S cycles through the modes.
+    pub sort: SortMode,
+    /// Order of the states when sorting by state, states left out go last.
+    pub state_order: Vec<DeviceState>,
+    /// Kinds of block devices listed, the others are left out entirely, also
+    /// from the commands that don't start the TUI, unlike with the runtime
+    /// filters.
+    pub device_kinds: Vec<BlockDeviceKind>,
+    /// Exit after this many seconds without a key being pressed.
+    pub idle_timeout_secs: Option<u64>,
+    /// Times a mount or unmount is retried after an error that usually goes
+    /// away by itself, like a timeout right after plugging a device in.
+    pub retries: u32,
+    /// Connect to udisks2 on the session bus instead of the system bus.
+    pub session_bus: bool,
+    /// Keys to bind to actions instead of their defaults, e.g.
+    /// `mount = ["Space"]`, see [`Keymap`](crate::keymap::Keymap).
+    pub keys: BTreeMap<Action, Vec<Key>>,
+    /// Where the default config was written to because there was none.
+    #[serde(skip)]
+    pub created: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mount_options: BTreeMap::new(),
+            enter_action: EnterAction::default(),
+            stay: false,
+            confirm_exit: false,
+            double_tap_ms: 300,
+            fstypes: BTreeMap::new(),
+            fstype_options: BTreeMap::new(),
+            own_files: false,
+            umask: None,
+            name_order: vec![NameSource::HintName, NameSource::Device],
+            long_names: false,
+            no_color: false,
+            announce: None,
+            idle_timeout_secs: None,
+            ascii: None,
+            bell: false,
+            success_command: None,
+            error_command: None,
+            created: None,
+            session_bus: false,
+            keys: BTreeMap::new(),
+            retries: 3,
+            compact: false,
+            drive_size: false,
+            columns: vec![
+                Column::Name,
+                Column::Label,
+                Column::MountPoint,
+                Column::Size,
+                Column::Usage,
+                Column::Status,
+                Column::Auto,
+                Column::LastMounted,
+            ],
+            sort: SortMode::default(),
+            state_order: vec![
+                DeviceState::Mounted,
+                DeviceState::UnmountedUnlocked,
+                DeviceState::Locked,
+                DeviceState::Unmounted,
+            ],
+            device_kinds: vec![
+                BlockDeviceKind::Filesystem,
+                BlockDeviceKind::Encrypted,
+                BlockDeviceKind::NoMedia,
+                BlockDeviceKind::Member,
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NameSource {
+    /// The `HintName` udev rules can set for a device.
+    HintName,
+    Label,
+    /// The device path, e.g. `/dev/sdb1`, or `/dev/mapper/luks-...` for an
+    /// unlocked LUKS device.
+    Device,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Column {
+    /// The name chosen by `name_order`.
+    Name,
+    Label,
+    MountPoint,
+    Size,
+    /// Bar of the space used, for mounted filesystems.
+    Usage,
+    /// Space used, for mounted filesystems.
+    Used,
+    Fstype,
+    Uuid,
+    /// How the drive is connected, e.g. `usb`.
+    Bus,
+    Status,
+    /// `A` for devices udisks2 hints should be mounted automatically.
+    Auto,
+    LastMounted,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortMode {
+    /// By drive, in the order udisks2 lists them.
+    #[default]
+    None,
+    Name,
+    /// Largest first.
+    Size,
+    /// By `state_order`, e.g. mounted devices on top.
+    State,
+}
+
+impl SortMode {
+    pub fn next(self) -> Self {
+        match self {
+            SortMode::None => SortMode::Name,
+            SortMode::Name => SortMode::Size,
+            SortMode::Size => SortMode::State,
+            SortMode::State => SortMode::None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnterAction {
+    /// Mount and exit, printing the mount point.
+    #[default]
+    MountAndExit,
+    Mount,
+    Details,
+    /// Mount and open the mount point in the file manager.
+    Open,
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        let mut config: Self = match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .wrap_err_with(|| format!("failed to parse {}", path.display()))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let mut config = Self::default();
+                match write_default(&path) {
+                    Ok(()) => config.created = Some(path.clone()),
+                    Err(err) => warn!("failed to create {}: {err}", path.display()),
+                }
+                config
+            }
+            Err(err) => {
+                return Err(err).wrap_err_with(|| format!("failed to read {}", path.display()))
+            }
+        };
+        if config.columns.is_empty() {
+            return Err(eyre!("{}: columns can't be empty", path.display()));
+        }
+        if config.ascii.is_none() {
+            config.ascii = Some(!supports_unicode());
+        }
+        if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            config.no_color = true;
+        }
+        Ok(config)
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|dir| dir.join("udiskstui").join("config.toml"))
+    }
+
+    /// Adds what the config sets for mounting the device with `uuid` to
+    /// `options`: its filesystem type, the owner of the files and the options
+    /// for each filesystem type, unless `options` already has them.
+    pub fn apply_mount_options(&self, options: &mut MountOptions, uuid: &str) {
+        if options.fstype.is_none() {
+            options.fstype = self.fstypes.get(uuid).cloned();
+        }
+        if self.own_files && options.owner.is_none() {
+            options.owner = Some(Owner::current(self.umask.clone()));
+        }
+        options.profiles.clone_from(&self.fstype_options);
+    }
+}
+
+/// Writes [`DEFAULT_CONFIG`] to `path`, unless something was created there
+/// meanwhile.
+fn write_default(path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::File::options()
+        .write(true)
+        .create_new(true)
+        .open(path)?
+        .write_all(DEFAULT_CONFIG.as_bytes())
+}
+
+/// Guesses whether the terminal can show Unicode from the locale and `TERM`.
+fn supports_unicode() -> bool {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .into_iter()
+        .filter_map(env::var_os)
+        .find(|value| !value.is_empty())
+        .map(|value| value.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let term = env::var("TERM").unwrap_or_default();
+    (locale.contains("utf-8") || locale.contains("utf8"))
+        && !matches!(term.as_str(), "dumb" | "vt100" | "vt102" | "vt220")
+}
@@ -1,18 +1,36 @@
 use std::{
     borrow::Cow,
-    ffi::{CStr, CString},
+    collections::HashMap,
+    env,
+    ffi::{CStr, OsString},
+    fmt::Display,
+    fs,
+    os::unix::ffi::OsStringExt,
+    path::{Path, PathBuf},
     str,
 };
 
-use color_eyre::Result;
-use humansize::{format_size, DECIMAL};
+use color_eyre::{
+    eyre::{eyre, Context},
+    Result,
+};
+use humansize::{format_size, BINARY, DECIMAL};
+use log::{debug, info, warn};
 use secstr::SecStr;
+use serde::Deserialize;
+use zvariant::OwnedObjectPath;
 
 use crate::{
-    app::{GuiDeviceInfo, Message},
+    bind_mount::BindMount,
+    btrfs,
+    config::NameSource,
+    luks,
+    mount_options::MountOptions,
+    partition_types,
     udisks2::{
-        BlockDevice, BlockDeviceKind, BlockProxy, Client, DriveProxy, EncryptedProxy,
-        FilesystemProxy,
+        BlockDevice, BlockDeviceKind, BlockProxy, Client, DriveAtaProxy, DriveProxy,
+        EncryptedProxy, FilesystemProxy, MDRaidProxy, PartitionProxy, PhysicalVolumeProxy,
+        VolumeGroupProxy,
     },
 };
 
@@ -22,12 +40,427 @@ pub struct Device {
     block_device: BlockDevice,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum DeviceState {
+    /// An empty card reader or drive, see [`BlockDeviceKind::NoMedia`].
+    NoMedia,
+    /// See [`BlockDeviceKind::Member`].
+    Member,
     Locked,
+    #[serde(rename = "unlocked")]
     UnmountedUnlocked,
     Mounted,
     Unmounted,
+    /// Querying the device failed or timed out while listing the devices,
+    /// see [`GuiDevice::error`].
+    Failed,
+}
+
+#[derive(Debug)]
+pub struct GuiDevice {
+    pub info: GuiDeviceInfo,
+    pub state: DeviceState,
+    /// Writes are impossible, e.g. because of an SD card's write-lock switch.
+    pub read_only: bool,
+    /// udisks2 hints that the device should be mounted as soon as it appears,
+    /// which desktop automounters follow.
+    pub auto: bool,
+    /// Set when the device is a partition.
+    pub partition: Option<PartitionInfo>,
+    /// Set when the device is on a drive, e.g. not for loop devices.
+    pub drive: Option<DriveInfo>,
+    /// Name of the RAID array or LVM volume group the device is a member of,
+    /// when known.
+    pub member_of: Option<String>,
+    /// Why the device couldn't be queried, when its state is
+    /// [`DeviceState::Failed`].
+    pub error: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DriveInfo {
+    pub path: String,
+    /// Vendor and model, or the last part of the path if both are empty.
+    pub name: String,
+    /// How the drive is connected, e.g. `usb`, empty if unknown.
+    pub bus: String,
+    /// Size in bytes, 0 without media.
+    pub size: u64,
+    /// Whether the drive or its media can be removed, e.g. a USB stick.
+    pub removable: bool,
+}
+
+#[derive(Debug)]
+pub struct PartitionInfo {
+    /// Human readable partition type, or the raw type if it's unknown.
+    pub type_name: String,
+    pub name: String,
+}
+
+#[derive(Debug)]
+pub struct GuiDeviceInfo {
+    pub name: String,
+    /// Canonical device path, unlike `name` not converted to UTF-8.
+    pub path: PathBuf,
+    /// The path udisks2 prefers showing, which differs from `name` for the
+    /// cleartext device of an unlocked LUKS device, e.g.
+    /// `/dev/mapper/luks-...`.
+    pub preferred_name: String,
+    /// `preferred_name` without the directory, with the UUID in
+    /// `luks-<uuid>` names cut short.
+    pub short_name: String,
+    /// Friendly name set by udev rules, usually empty.
+    pub hint_name: String,
+    pub label: String,
+    pub uuid: String,
+    pub fstype: String,
+    /// Size in bytes.
+    pub size: u64,
+    pub mount_point: String,
+    /// Space used by the filesystem, set when it is mounted.
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Usage {
+    /// Bytes used.
+    pub used: u64,
+    /// Size of the filesystem in bytes.
+    pub total: u64,
+}
+
+#[derive(Debug)]
+pub enum DeviceMessage {
+    Mounted(usize, String),
+    Unmounted(usize),
+    Locked(usize),
+    UnmountedAndLocked(usize, GuiDeviceInfo),
+    UnlockedAndMounted(usize, String, GuiDeviceInfo),
+    /// An encrypted device found unlocked by something else was mounted, or
+    /// was mounted already, with the info of its cleartext device.
+    MountedUnlocked(usize, String, GuiDeviceInfo),
+    /// An encrypted device holding no filesystem itself was unlocked, so the
+    /// ones inside it can be listed.
+    UnlockedContainer(usize, GuiDeviceInfo),
+    /// An encrypted device holding no filesystem itself was locked, so the
+    /// ones inside it are gone.
+    LockedContainer(usize),
+    Subvolumes(usize, Vec<String>),
+    /// A plain text description of the device for bug reports.
+    Diagnostics(usize, String),
+    AlreadyMounted(usize, String),
+    AlreadyUnmounted(usize),
+    AlreadyLocked(usize),
+    Devices(Vec<GuiDevice>, Vec<Device>),
+    /// Unlocking needs a passphrase, with the options to mount with once
+    /// unlocked, or `None` to only unlock, and the types of the tokens
+    /// enrolled in the LUKS header, if they could be read.
+    PassphraseRequired(usize, Option<MountOptions>, Vec<String>),
+    /// An encrypted device was unlocked without mounting it, with the info
+    /// of its cleartext device.
+    Unlocked(usize, GuiDeviceInfo),
+    /// The pending writes were flushed, so the device can be ejected.
+    Flushed(usize),
+    Ejected(usize),
+    EraseMethods(usize, Vec<EraseMethod>),
+    Wiped(usize),
+    /// The whole disk the device is on, for creating a partition table.
+    WholeDisk(usize, Disk),
+    /// An empty partition table was created on the named disk.
+    PartitionTableCreated(usize, String),
+    BindMounted(usize, BindMount),
+    LabelChanged(usize, String),
+    /// The device at the index was re-queried, `None` if that failed.
+    DeviceRefreshed(usize, Option<GuiDevice>),
+    Resized(usize, u64),
+    /// The filesystem was checked, and is consistent if `true`.
+    Checked(usize, bool),
+    /// The filesystem was repaired, successfully if `true`.
+    Repaired(usize, bool),
+    /// The user now owns the filesystem, every file on it if `true`.
+    OwnershipTaken(usize, bool),
+    /// Removable drives were ejected, with the paths of the devices on the
+    /// ejected drives and the errors of the ones that failed.
+    DrivesEjected(Vec<OwnedObjectPath>, Vec<String>),
+    /// Every unlocked encrypted device was locked, with the messages of the
+    /// ones that were, by object path since the list may have changed
+    /// meanwhile, and the errors of the ones that failed.
+    LockedAll(Vec<(OwnedObjectPath, DeviceMessage)>, Vec<String>),
+}
+
+impl DeviceMessage {
+    /// The index of the device the message is about, to point it at the same
+    /// device once the list changed. `None` for messages about every device.
+    pub fn idx_mut(&mut self) -> Option<&mut usize> {
+        match self {
+            DeviceMessage::Mounted(idx, ..)
+            | DeviceMessage::Unmounted(idx)
+            | DeviceMessage::Locked(idx)
+            | DeviceMessage::UnmountedAndLocked(idx, ..)
+            | DeviceMessage::UnlockedAndMounted(idx, ..)
+            | DeviceMessage::MountedUnlocked(idx, ..)
+            | DeviceMessage::UnlockedContainer(idx, ..)
+            | DeviceMessage::LockedContainer(idx)
+            | DeviceMessage::Subvolumes(idx, ..)
+            | DeviceMessage::Diagnostics(idx, ..)
+            | DeviceMessage::AlreadyMounted(idx, ..)
+            | DeviceMessage::AlreadyUnmounted(idx)
+            | DeviceMessage::AlreadyLocked(idx)
+            | DeviceMessage::PassphraseRequired(idx, ..)
+            | DeviceMessage::Unlocked(idx, ..)
+            | DeviceMessage::Flushed(idx)
+            | DeviceMessage::Ejected(idx)
+            | DeviceMessage::EraseMethods(idx, ..)
+            | DeviceMessage::Wiped(idx)
+            | DeviceMessage::WholeDisk(idx, ..)
+            | DeviceMessage::PartitionTableCreated(idx, ..)
+            | DeviceMessage::BindMounted(idx, ..)
+            | DeviceMessage::LabelChanged(idx, ..)
+            | DeviceMessage::DeviceRefreshed(idx, ..)
+            | DeviceMessage::Resized(idx, ..)
+            | DeviceMessage::Checked(idx, ..)
+            | DeviceMessage::Repaired(idx, ..)
+            | DeviceMessage::OwnershipTaken(idx, ..) => Some(idx),
+            DeviceMessage::Devices(..)
+            | DeviceMessage::DrivesEjected(..)
+            | DeviceMessage::LockedAll(..) => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SizeMode {
+    /// Powers of 1000, e.g. `32 GB`.
+    #[default]
+    Decimal,
+    /// Powers of 1024, e.g. `29.8 GiB`.
+    Binary,
+    Bytes,
+}
+
+impl SizeMode {
+    pub fn next(self) -> Self {
+        match self {
+            SizeMode::Decimal => SizeMode::Binary,
+            SizeMode::Binary => SizeMode::Bytes,
+            SizeMode::Bytes => SizeMode::Decimal,
+        }
+    }
+
+    pub fn format(self, size: u64) -> String {
+        match self {
+            SizeMode::Decimal => format_size(size, DECIMAL),
+            SizeMode::Binary => format_size(size, BINARY),
+            SizeMode::Bytes => format!("{size} B"),
+        }
+    }
+}
+
+/// Parses a size like `32G`, `1.5 GiB`, `500 MB`, `1000000` or `50%`, where
+/// a number alone is in bytes, single letter units are binary and
+/// percentages are of `whole`, e.g. the current size of the device.
+pub fn parse_size(input: &str, whole: u64) -> Result<u64, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    let not_a_size = || format!("{input:?} is not a size");
+    // the number as an integer scaled by `10^fraction.len()`, so fractions of
+    // huge sizes don't lose precision like they would in a float
+    let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if integer.is_empty() && fraction.is_empty() || fraction.contains('.') {
+        return Err(not_a_size());
+    }
+    // digits past what a u64 can hold can't change the size by a byte
+    let fraction = &fraction[..fraction.len().min(19)];
+    let integer: u128 = match integer {
+        "" => 0,
+        integer => integer.parse::<u64>().map_err(|_| not_a_size())?.into(),
+    };
+    let scale = 10u128.pow(fraction.len() as u32);
+    let scaled = integer * scale
+        + match fraction {
+            "" => 0,
+            fraction => fraction.parse::<u128>().map_err(|_| not_a_size())?,
+        };
+    let too_large = || format!("{input} is too large");
+
+    let unit = unit.trim().to_ascii_lowercase();
+    if unit == "%" {
+        let size = scaled.checked_mul(whole.into()).ok_or_else(too_large)? / (100 * scale);
+        return u64::try_from(size).map_err(|_| too_large());
+    }
+    let multiplier: u64 = match unit.as_str() {
+        "" | "b" => 1,
+        "kb" => 1000,
+        "mb" => 1000u64.pow(2),
+        "gb" => 1000u64.pow(3),
+        "tb" => 1000u64.pow(4),
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        unit => return Err(format!("unknown unit {unit:?}")),
+    };
+    let size = scaled
+        .checked_mul(multiplier.into())
+        .ok_or_else(too_large)?
+        / scale;
+    u64::try_from(size).map_err(|_| too_large())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EraseMethod {
+    /// Only remove the filesystem/partition signatures.
+    Signatures,
+    Zero,
+    AtaSecureErase,
+    AtaSecureEraseEnhanced,
+}
+
+impl EraseMethod {
+    /// Value passed as the `erase` option of `Block.Format`.
+    pub fn option(&self) -> Option<&'static str> {
+        match self {
+            EraseMethod::Signatures => None,
+            EraseMethod::Zero => Some("zero"),
+            EraseMethod::AtaSecureErase => Some("ata-secure-erase"),
+            EraseMethod::AtaSecureEraseEnhanced => Some("ata-secure-erase-enhanced"),
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            EraseMethod::Signatures => "Wipe signatures only (fast)",
+            EraseMethod::Zero => "Overwrite with zeroes (slow)",
+            EraseMethod::AtaSecureErase => "ATA secure erase (whole drive)",
+            EraseMethod::AtaSecureEraseEnhanced => "ATA enhanced secure erase (whole drive)",
+        }
+    }
+}
+
+/// The whole-disk block device a device is on.
+#[derive(Debug, Clone)]
+pub struct Disk {
+    pub path: OwnedObjectPath,
+    pub name: String,
+    /// Size in bytes.
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionTableType {
+    Gpt,
+    /// MBR.
+    Dos,
+}
+
+impl PartitionTableType {
+    pub const ALL: [PartitionTableType; 2] = [PartitionTableType::Gpt, PartitionTableType::Dos];
+
+    /// Type passed to `Block.Format`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PartitionTableType::Gpt => "gpt",
+            PartitionTableType::Dos => "dos",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            PartitionTableType::Gpt => "GPT (recommended)",
+            PartitionTableType::Dos => "MBR (DOS, for old systems)",
+        }
+    }
+}
+
+/// Lists the block devices udisks2 knows about that can be mounted or
+/// unlocked.
+pub async fn list_devices(client: &Client) -> Result<Vec<Device>> {
+    let block_devices = client.get_block_devices().await?;
+    let mut devices = Vec::with_capacity(block_devices.len());
+    for block_device in block_devices {
+        devices.push(Device::new(client, block_device).await?);
+    }
+    Ok(devices)
+}
+
+/// Groups the indices of the devices on removable or ejectable drives by
+/// drive.
+pub async fn removable_drives(
+    client: &Client,
+    devices: &[Device],
+) -> Result<Vec<(OwnedObjectPath, Vec<usize>)>> {
+    let mut drives: Vec<(OwnedObjectPath, Vec<usize>)> = Vec::new();
+    for (idx, device) in devices.iter().enumerate() {
+        let drive = device.drive().await?;
+        if drive.len() <= 1 {
+            continue;
+        }
+        if let Some((_, indices)) = drives.iter_mut().find(|(path, _)| *path == drive) {
+            indices.push(idx);
+            continue;
+        }
+        let proxy = client.cached::<DriveProxy>(&drive).await?;
+        if proxy.removable().await? || proxy.ejectable().await? {
+            drives.push((drive, vec![idx]));
+        }
+    }
+    Ok(drives)
+}
+
+/// Bytes waiting to be written back to the disks, `Dirty` and `Writeback`
+/// in `/proc/meminfo`.
+pub fn pending_writes() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let kib: u64 = meminfo
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            if key != "Dirty" && key != "Writeback" {
+                return None;
+            }
+            value.trim().strip_suffix(" kB")?.parse::<u64>().ok()
+        })
+        .sum();
+    Some(kib * 1024)
+}
+
+/// Writes the data cached in memory to the disks, which can take a while
+/// after copying a lot to a slow USB drive. Ejecting before that finishes
+/// loses the data.
+pub async fn flush() -> Result<()> {
+    debug!("sync");
+    tokio::task::spawn_blocking(rustix::fs::sync).await?;
+    Ok(())
+}
+
+/// Unmounts and locks the devices at `indices`, which must all be on
+/// `drive`, then ejects it.
+pub async fn eject_drive(
+    client: &Client,
+    devices: &[Device],
+    drive: &OwnedObjectPath,
+    indices: &[usize],
+) -> Result<()> {
+    let name = drive.rsplit('/').next().unwrap_or(drive);
+    info!("ejecting {drive}");
+    for &idx in indices {
+        devices[idx]
+            .unmount(idx)
+            .await
+            .wrap_err_with(|| format!("failed to eject {name}"))?;
+    }
+    flush().await?;
+    let proxy = client.cached::<DriveProxy>(drive).await?;
+    debug!("Drive.Eject on {drive}");
+    proxy
+        .eject(Default::default())
+        .await
+        .wrap_err_with(|| format!("failed to eject {name}"))?;
+    Ok(())
 }
 
 impl Device {
@@ -39,155 +472,714 @@ impl Device {
         })
     }
 
-    pub async fn mount(&self, idx: usize, passphrase: Option<SecStr>) -> Result<Message> {
-        let object_path = if let BlockDeviceKind::Encrypted = self.block_device.kind {
-            let proxy = EncryptedProxy::builder(self.client.conn())
-                .path(&self.block_device.path)?
-                .build()
+    pub fn block_device(&self) -> &BlockDevice {
+        &self.block_device
+    }
+
+    /// Drops the cached proxies of the device and of its cleartext device, if
+    /// unlocked, in case their properties went stale.
+    pub async fn forget_cached(&self) -> Result<()> {
+        let path = &self.block_device.path;
+        self.client.forget(path);
+        if let BlockDeviceKind::Encrypted = self.block_device.kind {
+            let proxy = self.client.cached::<EncryptedProxy>(path).await?;
+            let cleartext_device = proxy.cleartext_device().await?;
+            if cleartext_device.len() > 1 {
+                self.client.forget(&cleartext_device);
+            }
+        }
+        Ok(())
+    }
+
+    /// Path of the drive the device is on, `/` if it has none.
+    pub async fn drive(&self) -> Result<OwnedObjectPath> {
+        let proxy = self
+            .client
+            .cached::<BlockProxy>(&self.block_device.path)
+            .await?;
+        Ok(proxy.drive().await?)
+    }
+
+    pub async fn mount(
+        &self,
+        idx: usize,
+        passphrase: Option<SecStr>,
+        options: &MountOptions,
+    ) -> Result<DeviceMessage> {
+        info!(
+            "mounting {} with options {:?}",
+            self.block_device.path, options.options
+        );
+        if let BlockDeviceKind::NoMedia = self.block_device.kind {
+            return Err(no_media());
+        }
+        if let BlockDeviceKind::Member = self.block_device.kind {
+            return Err(member());
+        }
+        if let BlockDeviceKind::Encrypted = self.block_device.kind {
+            let proxy = self
+                .client
+                .cached::<EncryptedProxy>(&self.block_device.path)
                 .await?;
             let cleartext_device = proxy.cleartext_device().await?;
             if cleartext_device.len() > 1 {
-                Cow::Owned(cleartext_device)
+                return self.mount_unlocked(idx, &cleartext_device, options).await;
             } else {
-                let mut passphrase = match passphrase {
-                    Some(p) => p,
-                    None => return Ok(Message::PassphraseRequired(idx)),
+                let Some(mut passphrase) = passphrase else {
+                    return self.passphrase_required(idx, Some(options.clone())).await;
                 };
+                // the filesystem type is only known once unlocked, so only
+                // what doesn't depend on it is checked before
+                options.validate_any()?;
+                debug!("Encrypted.Unlock on {}", self.block_device.path);
                 let cleartext_device = proxy
                     .unlock(str::from_utf8(passphrase.unsecure())?, Default::default())
                     .await?;
                 passphrase.zero_out();
-                let proxy = FilesystemProxy::builder(self.client.conn())
-                    .path(&cleartext_device)?
-                    .build()
-                    .await?;
-                let mount_point = proxy.mount(Default::default()).await?;
+                let result = async {
+                    let block_proxy = self.client.cached::<BlockProxy>(&cleartext_device).await?;
+                    if !self.client.has_filesystem(&cleartext_device).await? {
+                        let info = GuiDeviceInfo::new(&block_proxy, String::new()).await?;
+                        return Ok(DeviceMessage::UnlockedContainer(idx, info));
+                    }
+                    let fstype = block_proxy.id_type().await?;
+                    let options = options.for_fstype(&fstype);
+                    options.validate(&fstype)?;
+                    let proxy = self
+                        .client
+                        .cached::<FilesystemProxy>(&cleartext_device)
+                        .await?;
+                    debug!("Filesystem.Mount on {cleartext_device}");
+                    let mount_point = proxy.mount(options.to_dbus()).await?;
 
-                let proxy = BlockProxy::builder(self.client.conn())
-                    .path(cleartext_device)?
-                    .build()
-                    .await?;
-                let name = Self::get_name(&proxy).await?;
-                let label = Self::get_label(&proxy).await?;
-                let size = Self::get_size(&proxy).await?;
-                return Ok(Message::UnlockedAndMounted(
-                    idx,
-                    mount_point.clone(),
-                    GuiDeviceInfo {
-                        name,
-                        label,
-                        size,
-                        mount_point,
-                    },
-                ));
+                    let info = GuiDeviceInfo::new(&block_proxy, mount_point.clone()).await?;
+                    Ok(DeviceMessage::UnlockedAndMounted(idx, mount_point, info))
+                }
+                .await;
+                if result.is_err() {
+                    // left locked as it was, instead of unlocked while shown
+                    // as locked
+                    debug!("Encrypted.Lock on {}", self.block_device.path);
+                    if let Err(err) = proxy.lock(Default::default()).await {
+                        warn!("failed to lock {} again: {err}", self.block_device.path);
+                    }
+                }
+                return result;
             }
-        } else {
-            Cow::Borrowed(&self.block_device.path)
-        };
+        }
 
-        let proxy = FilesystemProxy::builder(self.client.conn())
-            .path(object_path.as_ref())?
-            .build()
-            .await?;
+        let object_path = &self.block_device.path;
+        let proxy = self.client.cached::<FilesystemProxy>(object_path).await?;
         if let Some(mount_point) = proxy.mount_points().await?.first() {
             let mount_point = CStr::from_bytes_with_nul(mount_point)?
                 .to_string_lossy()
                 .to_string();
-            Ok(Message::AlreadyMounted(idx, mount_point))
+            Ok(DeviceMessage::AlreadyMounted(idx, mount_point))
         } else {
-            let mount_point = proxy.mount(Default::default()).await?;
-            Ok(Message::Mounted(idx, mount_point))
+            let block_proxy = self.client.cached::<BlockProxy>(object_path).await?;
+            let fstype = block_proxy.id_type().await?;
+            let options = options.for_fstype(&fstype);
+            options.validate(&fstype)?;
+            debug!("Filesystem.Mount on {object_path}");
+            let mount_point = proxy.mount(options.to_dbus()).await?;
+            Ok(DeviceMessage::Mounted(idx, mount_point))
         }
     }
 
-    pub async fn unmount(&self, idx: usize) -> Result<Message> {
+    /// Unlocks an encrypted device without mounting its filesystem, e.g. to
+    /// run other tools on the cleartext device.
+    pub async fn unlock(&self, idx: usize, passphrase: Option<SecStr>) -> Result<DeviceMessage> {
+        info!("unlocking {}", self.block_device.path);
+        match self.block_device.kind {
+            BlockDeviceKind::Encrypted => {}
+            BlockDeviceKind::NoMedia => return Err(no_media()),
+            BlockDeviceKind::Member => return Err(member()),
+            BlockDeviceKind::Filesystem => return Err(eyre!("the device isn't encrypted")),
+        }
+        let proxy = self
+            .client
+            .cached::<EncryptedProxy>(&self.block_device.path)
+            .await?;
+        let mut cleartext_device = proxy.cleartext_device().await?;
+        if cleartext_device.len() <= 1 {
+            let Some(mut passphrase) = passphrase else {
+                return self.passphrase_required(idx, None).await;
+            };
+            debug!("Encrypted.Unlock on {}", self.block_device.path);
+            cleartext_device = proxy
+                .unlock(str::from_utf8(passphrase.unsecure())?, Default::default())
+                .await?;
+            passphrase.zero_out();
+        }
+        let block_proxy = self.client.cached::<BlockProxy>(&cleartext_device).await?;
+        let info = GuiDeviceInfo::new(&block_proxy, String::new()).await?;
+        if !self.client.has_filesystem(&cleartext_device).await? {
+            return Ok(DeviceMessage::UnlockedContainer(idx, info));
+        }
+        Ok(DeviceMessage::Unlocked(idx, info))
+    }
+
+    /// Asks for the passphrase of this encrypted device, noting the tokens
+    /// enrolled in its LUKS header.
+    async fn passphrase_required(
+        &self,
+        idx: usize,
+        options: Option<MountOptions>,
+    ) -> Result<DeviceMessage> {
+        let block_proxy = self
+            .client
+            .cached::<BlockProxy>(&self.block_device.path)
+            .await?;
+        let device = Device::get_name(&block_proxy).await?;
+        let tokens = luks::token_types(Path::new(&device)).unwrap_or_else(|err| {
+            debug!("can't read the LUKS tokens of {device}: {err}");
+            Vec::new()
+        });
+        Ok(DeviceMessage::PassphraseRequired(idx, options, tokens))
+    }
+
+    /// Mounts the cleartext device of an encrypted device that was unlocked
+    /// by something else, unless it's mounted already, e.g. by the same tool.
+    async fn mount_unlocked(
+        &self,
+        idx: usize,
+        cleartext_device: &OwnedObjectPath,
+        options: &MountOptions,
+    ) -> Result<DeviceMessage> {
+        let block_proxy = self.client.cached::<BlockProxy>(cleartext_device).await?;
+        if !self.client.has_filesystem(cleartext_device).await? {
+            return Err(eyre!(
+                "{} holds no filesystem, mount the ones inside it instead",
+                Device::get_name(&block_proxy).await?
+            ));
+        }
+        let proxy = self
+            .client
+            .cached::<FilesystemProxy>(cleartext_device)
+            .await?;
+        let mount_point = match proxy.mount_points().await?.first() {
+            Some(mount_point) => CStr::from_bytes_with_nul(mount_point)?
+                .to_string_lossy()
+                .to_string(),
+            None => {
+                let fstype = block_proxy.id_type().await?;
+                let options = options.for_fstype(&fstype);
+                options.validate(&fstype)?;
+                debug!("Filesystem.Mount on {cleartext_device}");
+                proxy.mount(options.to_dbus()).await?
+            }
+        };
+        let info = GuiDeviceInfo::new(&block_proxy, mount_point.clone()).await?;
+        Ok(DeviceMessage::MountedUnlocked(idx, mount_point, info))
+    }
+
+    pub async fn unmount(&self, idx: usize) -> Result<DeviceMessage> {
+        info!("unmounting {}", self.block_device.path);
         match self.block_device.kind {
             BlockDeviceKind::Filesystem => {
-                let proxy = FilesystemProxy::builder(self.client.conn())
-                    .path(&self.block_device.path)?
-                    .build()
+                let proxy = self
+                    .client
+                    .cached::<FilesystemProxy>(&self.block_device.path)
                     .await?;
                 if proxy.mount_points().await?.is_empty() {
-                    Ok(Message::AlreadyUnmounted(idx))
+                    Ok(DeviceMessage::AlreadyUnmounted(idx))
                 } else {
+                    debug!("Filesystem.Unmount on {}", self.block_device.path);
                     proxy.unmount(Default::default()).await?;
-                    Ok(Message::Unmounted(idx))
+                    Ok(DeviceMessage::Unmounted(idx))
                 }
             }
             BlockDeviceKind::Encrypted => {
-                let proxy = EncryptedProxy::builder(self.client.conn())
-                    .path(&self.block_device.path)?
-                    .build()
+                let proxy = self
+                    .client
+                    .cached::<EncryptedProxy>(&self.block_device.path)
                     .await?;
                 let cleartext_device = proxy.cleartext_device().await?;
                 if cleartext_device.len() > 1 {
-                    let filesystem_proxy = FilesystemProxy::builder(self.client.conn())
-                        .path(cleartext_device)?
-                        .build()
+                    if !self.client.has_filesystem(&cleartext_device).await? {
+                        debug!("Encrypted.Lock on {}", self.block_device.path);
+                        proxy.lock(Default::default()).await?;
+                        return Ok(DeviceMessage::LockedContainer(idx));
+                    }
+                    let filesystem_proxy = self
+                        .client
+                        .cached::<FilesystemProxy>(&cleartext_device)
                         .await?;
                     if filesystem_proxy.mount_points().await?.is_empty() {
+                        debug!("Encrypted.Lock on {}", self.block_device.path);
                         proxy.lock(Default::default()).await?;
-                        return Ok(Message::Locked(idx));
+                        return Ok(DeviceMessage::Locked(idx));
                     }
+                    debug!("Filesystem.Unmount on {cleartext_device}");
                     filesystem_proxy.unmount(Default::default()).await?;
+                    debug!("Encrypted.Lock on {}", self.block_device.path);
                     proxy.lock(Default::default()).await?;
 
-                    let proxy = BlockProxy::builder(self.client.conn())
-                        .path(&self.block_device.path)?
-                        .build()
+                    let proxy = self
+                        .client
+                        .cached::<BlockProxy>(&self.block_device.path)
                         .await?;
-                    let name = Self::get_name(&proxy).await?;
-                    let label = Self::get_label(&proxy).await?;
-                    let size = Self::get_size(&proxy).await?;
-                    let info = GuiDeviceInfo {
-                        name,
-                        label,
-                        size,
-                        mount_point: String::new(),
-                    };
-                    Ok(Message::UnmountedAndLocked(idx, info))
+                    let info = GuiDeviceInfo::new(&proxy, String::new()).await?;
+                    Ok(DeviceMessage::UnmountedAndLocked(idx, info))
                 } else {
-                    Ok(Message::AlreadyLocked(idx))
+                    Ok(DeviceMessage::AlreadyLocked(idx))
                 }
             }
+            BlockDeviceKind::NoMedia => Err(no_media()),
+            BlockDeviceKind::Member => Err(member()),
         }
     }
 
-    pub async fn eject(&self, idx: usize) -> Result<Message> {
-        let proxy = BlockProxy::builder(self.client.conn())
-            .path(&self.block_device.path)?
-            .build()
+    pub async fn eject(&self, idx: usize) -> Result<DeviceMessage> {
+        info!("ejecting the drive of {}", self.block_device.path);
+        let proxy = self
+            .client
+            .cached::<BlockProxy>(&self.block_device.path)
             .await?;
         let drive = proxy.drive().await?;
-        let proxy = DriveProxy::builder(self.client.conn())
-            .path(drive)?
-            .build()
-            .await?;
+        debug!("Drive.Eject on {drive}");
+        let proxy = self.client.cached::<DriveProxy>(&drive).await?;
         proxy.eject(Default::default()).await?;
-        Ok(Message::Ejected(idx))
+        Ok(DeviceMessage::Ejected(idx))
+    }
+
+    pub async fn erase_methods(&self, idx: usize) -> Result<DeviceMessage> {
+        let mut methods = vec![EraseMethod::Signatures, EraseMethod::Zero];
+
+        // ATA secure erase always wipes the whole drive, so only offer it when
+        // the selected block device is the whole drive and not a partition.
+        let is_partition = self
+            .client
+            .interfaces(&self.block_device.path)
+            .await?
+            .iter()
+            .any(|interface| interface == "org.freedesktop.UDisks2.Partition");
+        if !is_partition {
+            let proxy = self
+                .client
+                .cached::<BlockProxy>(&self.block_device.path)
+                .await?;
+            let drive = proxy.drive().await?;
+            if drive.len() > 1 {
+                let proxy = self.client.cached::<DriveAtaProxy>(&drive).await?;
+                if !proxy.security_frozen().await.unwrap_or(true) {
+                    if proxy.security_erase_unit_minutes().await.unwrap_or(0) > 0 {
+                        methods.push(EraseMethod::AtaSecureErase);
+                    }
+                    if proxy
+                        .security_enhanced_erase_unit_minutes()
+                        .await
+                        .unwrap_or(0)
+                        > 0
+                    {
+                        methods.push(EraseMethod::AtaSecureEraseEnhanced);
+                    }
+                }
+            }
+        }
+
+        Ok(DeviceMessage::EraseMethods(idx, methods))
+    }
+
+    pub async fn wipe(&self, idx: usize, method: EraseMethod) -> Result<DeviceMessage> {
+        info!("wiping {} using {method:?}", self.block_device.path);
+        let proxy = self
+            .client
+            .cached::<BlockProxy>(&self.block_device.path)
+            .await?;
+        let mut options = HashMap::new();
+        if let Some(erase) = method.option() {
+            options.insert("erase", erase.into());
+        }
+        debug!("Block.Format on {}", self.block_device.path);
+        proxy.format("empty", options).await?;
+        Ok(DeviceMessage::Wiped(idx))
+    }
+
+    /// Finds the whole disk the device is a partition of, or the device
+    /// itself if it's not a partition, e.g. a USB stick without partitions.
+    pub async fn whole_disk(&self, idx: usize) -> Result<DeviceMessage> {
+        let path = &self.block_device.path;
+        let is_partition = self
+            .client
+            .interfaces(path)
+            .await?
+            .iter()
+            .any(|interface| interface == "org.freedesktop.UDisks2.Partition");
+        let disk = if is_partition {
+            self.client
+                .cached::<PartitionProxy>(path)
+                .await?
+                .table()
+                .await?
+        } else {
+            path.clone()
+        };
+        let proxy = self.client.cached::<BlockProxy>(&disk).await?;
+        Ok(DeviceMessage::WholeDisk(
+            idx,
+            Disk {
+                name: Device::get_name(&proxy).await?,
+                size: Device::get_size(&proxy).await?,
+                path: disk,
+            },
+        ))
+    }
+
+    /// Replaces everything on `disk` with an empty partition table,
+    /// unmounting and locking what is on it first.
+    pub async fn create_partition_table(
+        &self,
+        idx: usize,
+        disk: &Disk,
+        table: PartitionTableType,
+    ) -> Result<DeviceMessage> {
+        info!(
+            "creating a {} partition table on {}",
+            table.name(),
+            disk.path
+        );
+        let proxy = self.client.cached::<BlockProxy>(&disk.path).await?;
+        let mut options = HashMap::new();
+        options.insert("tear-down", true.into());
+        debug!("Block.Format on {}", disk.path);
+        proxy.format(table.name(), options).await?;
+        Ok(DeviceMessage::PartitionTableCreated(idx, disk.name.clone()))
+    }
+
+    /// Resizes the device to `size` bytes: the partition and the filesystem in
+    /// it, or for encrypted devices also the encrypted volume in between. They
+    /// are resized in the order that keeps each one fitting in the one
+    /// containing it.
+    pub async fn resize(&self, idx: usize, size: u64) -> Result<DeviceMessage> {
+        info!("resizing {} to {size} bytes", self.block_device.path);
+        let path = &self.block_device.path;
+        let block_proxy = self.client.cached::<BlockProxy>(path).await?;
+        let current = block_proxy.size().await?;
+        let partition_proxy = if self
+            .client
+            .interfaces(path)
+            .await?
+            .iter()
+            .any(|interface| interface == "org.freedesktop.UDisks2.Partition")
+        {
+            Some(self.client.cached::<PartitionProxy>(path).await?)
+        } else {
+            None
+        };
+        let grow = size > current;
+
+        match self.block_device.kind {
+            BlockDeviceKind::Filesystem => {
+                let filesystem_proxy = self.client.cached::<FilesystemProxy>(path).await?;
+                if !grow && !filesystem_proxy.mount_points().await?.is_empty() {
+                    return Err(eyre!("unmount the device before shrinking it"));
+                }
+                match partition_proxy {
+                    Some(partition_proxy) if grow => {
+                        debug!("Partition.Resize on {path}");
+                        partition_proxy.resize(size, Default::default()).await?;
+                        debug!("Filesystem.Resize on {path}");
+                        filesystem_proxy.resize(0, Default::default()).await?;
+                    }
+                    Some(partition_proxy) => {
+                        debug!("Filesystem.Resize on {path}");
+                        filesystem_proxy.resize(size, Default::default()).await?;
+                        debug!("Partition.Resize on {path}");
+                        partition_proxy.resize(size, Default::default()).await?;
+                    }
+                    None => {
+                        debug!("Filesystem.Resize on {path}");
+                        filesystem_proxy.resize(size, Default::default()).await?;
+                    }
+                }
+            }
+            BlockDeviceKind::NoMedia => return Err(no_media()),
+            BlockDeviceKind::Member => return Err(member()),
+            BlockDeviceKind::Encrypted => {
+                if !grow {
+                    return Err(eyre!("shrinking encrypted devices is not supported"));
+                }
+                let encrypted_proxy = self.client.cached::<EncryptedProxy>(path).await?;
+                let cleartext_device = encrypted_proxy.cleartext_device().await?;
+                if cleartext_device.len() <= 1 {
+                    return Err(eyre!("unlock the device before resizing it"));
+                }
+                if let Some(partition_proxy) = partition_proxy {
+                    debug!("Partition.Resize on {path}");
+                    partition_proxy.resize(size, Default::default()).await?;
+                }
+                debug!("Encrypted.Resize on {path}");
+                encrypted_proxy.resize(0, Default::default()).await?;
+                debug!("Filesystem.Resize on {cleartext_device}");
+                self.client
+                    .cached::<FilesystemProxy>(&cleartext_device)
+                    .await?
+                    .resize(0, Default::default())
+                    .await?;
+            }
+        }
+        Ok(DeviceMessage::Resized(idx, size))
+    }
+
+    /// Checks the device's filesystem, or repairs it if `repair` is set,
+    /// unmounting it first since udisks2 refuses otherwise. For encrypted
+    /// devices it's the filesystem inside, so they must be unlocked first.
+    pub async fn check(&self, idx: usize, repair: bool) -> Result<DeviceMessage> {
+        info!(
+            "{} {}",
+            if repair { "repairing" } else { "checking" },
+            self.block_device.path
+        );
+        let object_path = match self.block_device.kind {
+            BlockDeviceKind::Filesystem => Cow::Borrowed(&self.block_device.path),
+            BlockDeviceKind::NoMedia => return Err(no_media()),
+            BlockDeviceKind::Member => return Err(member()),
+            BlockDeviceKind::Encrypted => {
+                let proxy = self
+                    .client
+                    .cached::<EncryptedProxy>(&self.block_device.path)
+                    .await?;
+                let cleartext_device = proxy.cleartext_device().await?;
+                if cleartext_device.len() <= 1 {
+                    return Err(eyre!("unlock the device before checking it"));
+                }
+                Cow::Owned(cleartext_device)
+            }
+        };
+        let proxy = self
+            .client
+            .cached::<FilesystemProxy>(object_path.as_ref())
+            .await?;
+        if !proxy.mount_points().await?.is_empty() {
+            debug!("Filesystem.Unmount on {object_path}");
+            proxy.unmount(Default::default()).await?;
+        }
+        if repair {
+            debug!("Filesystem.Repair on {object_path}");
+            Ok(DeviceMessage::Repaired(
+                idx,
+                proxy.repair(Default::default()).await?,
+            ))
+        } else {
+            debug!("Filesystem.Check on {object_path}");
+            Ok(DeviceMessage::Checked(
+                idx,
+                proxy.check(Default::default()).await?,
+            ))
+        }
+    }
+
+    /// Lists the subvolumes of a btrfs filesystem by mounting its top level
+    /// for a moment, as a mount of another subvolume may not contain them.
+    pub async fn btrfs_subvolumes(&self, idx: usize) -> Result<DeviceMessage> {
+        info!("listing the subvolumes of {}", self.block_device.path);
+        let object_path = match self.block_device.kind {
+            BlockDeviceKind::Filesystem => Cow::Borrowed(&self.block_device.path),
+            BlockDeviceKind::NoMedia => return Err(no_media()),
+            BlockDeviceKind::Member => return Err(member()),
+            BlockDeviceKind::Encrypted => {
+                let proxy = self
+                    .client
+                    .cached::<EncryptedProxy>(&self.block_device.path)
+                    .await?;
+                let cleartext_device = proxy.cleartext_device().await?;
+                if cleartext_device.len() <= 1 {
+                    return Err(eyre!("unlock the device before choosing a subvolume"));
+                }
+                Cow::Owned(cleartext_device)
+            }
+        };
+        let proxy = self
+            .client
+            .cached::<FilesystemProxy>(object_path.as_ref())
+            .await?;
+        if !proxy.mount_points().await?.is_empty() {
+            return Err(eyre!("unmount the device before choosing a subvolume"));
+        }
+        debug!("Filesystem.Mount on {object_path} for listing subvolumes");
+        let options = MountOptions::with_options("subvolid=5");
+        let mount_point = proxy.mount(options.to_dbus()).await?;
+        let subvolumes =
+            tokio::task::spawn_blocking(move || btrfs::subvolumes(Path::new(&mount_point))).await;
+        debug!("Filesystem.Unmount on {object_path}");
+        proxy.unmount(Default::default()).await?;
+        Ok(DeviceMessage::Subvolumes(idx, subvolumes?))
+    }
+
+    /// Describes the device and what udisks2 knows about it, for bug reports.
+    pub async fn diagnostics(&self, idx: usize) -> Result<DeviceMessage> {
+        let path = &self.block_device.path;
+        let proxy = self.client.cached::<BlockProxy>(path).await?;
+        let mut lines = vec![
+            format!("udiskstui {}", env!("CARGO_PKG_VERSION")),
+            format!(
+                "Device: {} ({path})",
+                Device::get_path(&proxy).await?.display()
+            ),
+            format!("Filesystem: {}", proxy.id_type().await?),
+            format!("UUID: {}", proxy.id_uuid().await?),
+            format!("Size: {} bytes", proxy.size().await?),
+            format!(
+                "State: {}",
+                Device::get_state(&self.client, &self.block_device).await?
+            ),
+        ];
+        let drive = proxy.drive().await?;
+        if drive.len() > 1 {
+            let drive_proxy = self.client.cached::<DriveProxy>(&drive).await?;
+            lines.push(format!(
+                "Drive: {} {} (bus: {}, removable: {})",
+                drive_proxy.vendor().await?,
+                drive_proxy.model().await?,
+                drive_proxy.connection_bus().await?,
+                drive_proxy.removable().await?
+            ));
+        }
+        lines.push(format!(
+            "Interfaces: {}",
+            self.client.interfaces(path).await?.join(", ")
+        ));
+        if let BlockDeviceKind::Encrypted = self.block_device.kind {
+            let proxy = self.client.cached::<EncryptedProxy>(path).await?;
+            let cleartext_device = proxy.cleartext_device().await?;
+            if cleartext_device.len() > 1 {
+                let cleartext_proxy = self.client.cached::<BlockProxy>(&cleartext_device).await?;
+                lines.push(format!(
+                    "Cleartext device: {} ({cleartext_device}), filesystem: {}",
+                    Device::get_path(&cleartext_proxy).await?.display(),
+                    cleartext_proxy.id_type().await?
+                ));
+                lines.push(format!(
+                    "Cleartext interfaces: {}",
+                    self.client.interfaces(&cleartext_device).await?.join(", ")
+                ));
+            }
+        }
+        Ok(DeviceMessage::Diagnostics(idx, lines.join("\n") + "\n"))
+    }
+
+    /// Sets the label of the device's filesystem, which for encrypted devices
+    /// is the one inside, so they must be unlocked first.
+    pub async fn set_label(&self, idx: usize, label: &str) -> Result<DeviceMessage> {
+        info!(
+            "setting the label of {} to {label:?}",
+            self.block_device.path
+        );
+        let object_path = match self.block_device.kind {
+            BlockDeviceKind::Filesystem => Cow::Borrowed(&self.block_device.path),
+            BlockDeviceKind::NoMedia => return Err(no_media()),
+            BlockDeviceKind::Member => return Err(member()),
+            BlockDeviceKind::Encrypted => {
+                let proxy = self
+                    .client
+                    .cached::<EncryptedProxy>(&self.block_device.path)
+                    .await?;
+                let cleartext_device = proxy.cleartext_device().await?;
+                if cleartext_device.len() <= 1 {
+                    return Err(eyre!("unlock the device before changing its label"));
+                }
+                Cow::Owned(cleartext_device)
+            }
+        };
+        let proxy = self
+            .client
+            .cached::<FilesystemProxy>(object_path.as_ref())
+            .await?;
+        debug!("Filesystem.SetLabel on {object_path}");
+        proxy.set_label(label, Default::default()).await?;
+        Ok(DeviceMessage::LabelChanged(idx, label.to_string()))
+    }
+
+    pub async fn take_ownership(&self, idx: usize, recursive: bool) -> Result<DeviceMessage> {
+        info!(
+            "taking ownership of {}{}",
+            self.block_device.path,
+            if recursive { " recursively" } else { "" }
+        );
+        let object_path = match self.block_device.kind {
+            BlockDeviceKind::Filesystem => Cow::Borrowed(&self.block_device.path),
+            BlockDeviceKind::NoMedia => return Err(no_media()),
+            BlockDeviceKind::Member => return Err(member()),
+            BlockDeviceKind::Encrypted => {
+                let proxy = self
+                    .client
+                    .cached::<EncryptedProxy>(&self.block_device.path)
+                    .await?;
+                let cleartext_device = proxy.cleartext_device().await?;
+                if cleartext_device.len() <= 1 {
+                    return Err(eyre!("unlock and mount the device before taking ownership"));
+                }
+                Cow::Owned(cleartext_device)
+            }
+        };
+        let proxy = self
+            .client
+            .cached::<FilesystemProxy>(object_path.as_ref())
+            .await?;
+        if proxy.mount_points().await?.is_empty() {
+            return Err(eyre!("mount the device before taking ownership"));
+        }
+        let mut options = HashMap::new();
+        options.insert("recursive", recursive.into());
+        debug!("Filesystem.TakeOwnership on {object_path}");
+        proxy.take_ownership(options).await?;
+        Ok(DeviceMessage::OwnershipTaken(idx, recursive))
     }
 
     pub async fn get_name(proxy: &BlockProxy<'_>) -> Result<String> {
-        let p = proxy.device().await?;
-        Ok(CString::from_vec_with_nul(p)?.to_string_lossy().to_string())
+        let bytes = Device::device_bytes(proxy).await?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// `Block.PreferredDevice`, or the name if it's unset.
+    pub async fn get_preferred_name(proxy: &BlockProxy<'_>) -> Result<String> {
+        let mut bytes = proxy.preferred_device().await?;
+        if let Some(nul) = bytes.iter().position(|&b| b == 0) {
+            bytes.truncate(nul);
+        }
+        if bytes.is_empty() {
+            return Device::get_name(proxy).await;
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    pub async fn get_path(proxy: &BlockProxy<'_>) -> Result<PathBuf> {
+        let path = PathBuf::from(OsString::from_vec(Device::device_bytes(proxy).await?));
+        if path.is_relative() {
+            return Ok(path);
+        }
+        Ok(fs::canonicalize(&path).unwrap_or(path))
+    }
+
+    /// `Block.Device` without the trailing NUL, or the last part of the object
+    /// path while udisks2 hasn't filled it in yet, e.g. for a loop device
+    /// that was just created.
+    async fn device_bytes(proxy: &BlockProxy<'_>) -> Result<Vec<u8>> {
+        let mut bytes = proxy.device().await?;
+        if let Some(nul) = bytes.iter().position(|&b| b == 0) {
+            bytes.truncate(nul);
+        }
+        if bytes.is_empty() {
+            let path = proxy.inner().path().as_str();
+            debug!("{path} has no device yet");
+            bytes = path.rsplit('/').next().unwrap_or(path).into();
+        }
+        Ok(bytes)
     }
 
     pub async fn get_label(proxy: &BlockProxy<'_>) -> Result<String> {
         Ok(proxy.id_label().await?)
     }
 
-    pub async fn get_size(proxy: &BlockProxy<'_>) -> Result<String> {
-        let size = proxy.size().await?;
-        Ok(format_size(size, DECIMAL))
+    pub async fn get_size(proxy: &BlockProxy<'_>) -> Result<u64> {
+        Ok(proxy.size().await?)
     }
 
     pub async fn get_state(client: &Client, block_device: &BlockDevice) -> Result<DeviceState> {
         match block_device.kind {
             BlockDeviceKind::Filesystem => {
-                let proxy = FilesystemProxy::builder(client.conn())
-                    .path(&block_device.path)?
-                    .build()
-                    .await?;
+                let proxy = client.cached::<FilesystemProxy>(&block_device.path).await?;
                 if proxy.mount_points().await?.is_empty() {
                     Ok(DeviceState::Unmounted)
                 } else {
@@ -195,16 +1187,13 @@ impl Device {
                 }
             }
             BlockDeviceKind::Encrypted => {
-                let proxy = EncryptedProxy::builder(client.conn())
-                    .path(&block_device.path)?
-                    .build()
-                    .await?;
+                let proxy = client.cached::<EncryptedProxy>(&block_device.path).await?;
                 let cleartext_device = proxy.cleartext_device().await?;
                 if cleartext_device.len() > 1 {
-                    let proxy = FilesystemProxy::builder(client.conn())
-                        .path(cleartext_device)?
-                        .build()
-                        .await?;
+                    if !client.has_filesystem(&cleartext_device).await? {
+                        return Ok(DeviceState::UnmountedUnlocked);
+                    }
+                    let proxy = client.cached::<FilesystemProxy>(&cleartext_device).await?;
                     if proxy.mount_points().await?.is_empty() {
                         Ok(DeviceState::UnmountedUnlocked)
                     } else {
@@ -214,6 +1203,360 @@ impl Device {
                     Ok(DeviceState::Locked)
                 }
             }
+            BlockDeviceKind::NoMedia => Ok(DeviceState::NoMedia),
+            BlockDeviceKind::Member => Ok(DeviceState::Member),
+        }
+    }
+}
+
+impl GuiDevice {
+    pub async fn new(client: &Client, block_device: &BlockDevice) -> Result<Self> {
+        let (path, mount_point) = match block_device.kind {
+            BlockDeviceKind::Filesystem => {
+                let filesystem_proxy = client.cached::<FilesystemProxy>(&block_device.path).await?;
+                let mount_point = match filesystem_proxy.mount_points().await?.first() {
+                    Some(mount_point) => CStr::from_bytes_with_nul(mount_point)?
+                        .to_string_lossy()
+                        .to_string(),
+                    None => String::new(),
+                };
+                (Cow::Borrowed(&block_device.path), mount_point)
+            }
+            BlockDeviceKind::Encrypted => {
+                let encrypted_proxy = client.cached::<EncryptedProxy>(&block_device.path).await?;
+                let cleartext_device = encrypted_proxy.cleartext_device().await?;
+                if cleartext_device.len() > 1 {
+                    let mount_point = if client.has_filesystem(&cleartext_device).await? {
+                        let filesystem_proxy =
+                            client.cached::<FilesystemProxy>(&cleartext_device).await?;
+                        match filesystem_proxy.mount_points().await?.first() {
+                            Some(mount_point) => CStr::from_bytes_with_nul(mount_point)?
+                                .to_string_lossy()
+                                .to_string(),
+                            None => String::new(),
+                        }
+                    } else {
+                        String::new()
+                    };
+                    (Cow::Owned(cleartext_device), mount_point)
+                } else {
+                    (Cow::Borrowed(&block_device.path), String::new())
+                }
+            }
+            BlockDeviceKind::NoMedia | BlockDeviceKind::Member => {
+                (Cow::Borrowed(&block_device.path), String::new())
+            }
+        };
+        let proxy = client.cached::<BlockProxy>(path.as_ref()).await?;
+        let info = GuiDeviceInfo::new(&proxy, mount_point).await?;
+        let state = Device::get_state(client, block_device).await?;
+        let read_only = proxy.read_only().await?;
+        let auto = client
+            .cached::<BlockProxy>(&block_device.path)
+            .await?
+            .hint_auto()
+            .await?;
+        let partition = PartitionInfo::new(client, block_device).await?;
+        let drive = DriveInfo::new(client, block_device).await?;
+        let member_of = match block_device.kind {
+            BlockDeviceKind::Member => member_of(client, &block_device.path).await,
+            _ => None,
+        };
+        Ok(Self {
+            info,
+            state,
+            read_only,
+            auto,
+            partition,
+            drive,
+            member_of,
+            error: None,
+        })
+    }
+
+    /// The state as shown, telling RAID members and LVM physical volumes
+    /// apart by their type.
+    pub fn status(&self) -> String {
+        match (self.state, self.info.fstype.as_str()) {
+            (DeviceState::Member, "linux_raid_member") => "RAID member".to_string(),
+            (DeviceState::Member, "LVM2_member") => "LVM PV".to_string(),
+            (state, _) => state.to_string(),
+        }
+    }
+
+    /// Placeholder for a device that couldn't be queried, named after its
+    /// object path, so it still shows up and can be reloaded.
+    pub fn failed(block_device: &BlockDevice, error: String) -> Self {
+        let path = block_device.path.as_str();
+        let name = path.rsplit('/').next().unwrap_or(path).to_string();
+        Self {
+            info: GuiDeviceInfo {
+                path: PathBuf::from(&name),
+                preferred_name: name.clone(),
+                short_name: name.clone(),
+                name,
+                hint_name: String::new(),
+                label: String::new(),
+                uuid: String::new(),
+                fstype: String::new(),
+                size: 0,
+                mount_point: String::new(),
+                usage: None,
+            },
+            state: DeviceState::Failed,
+            read_only: false,
+            auto: false,
+            partition: None,
+            drive: None,
+            member_of: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// Name of the RAID array or LVM volume group the member device at `path` is
+/// part of, `None` if it isn't assembled or activated, or can't be told.
+async fn member_of(client: &Client, path: &OwnedObjectPath) -> Option<String> {
+    let proxy = client.cached::<BlockProxy>(path).await.ok()?;
+    let array = proxy.mdraid_member().await.ok()?;
+    if array.len() > 1 {
+        let proxy = client.cached::<MDRaidProxy>(&array).await.ok()?;
+        return proxy.name().await.ok();
+    }
+    let proxy = client.cached::<PhysicalVolumeProxy>(path).await.ok()?;
+    let group = proxy.volume_group().await.ok()?;
+    if group.len() <= 1 {
+        return None;
+    }
+    let proxy = client.cached::<VolumeGroupProxy>(&group).await.ok()?;
+    proxy.name().await.ok()
+}
+
+impl PartitionInfo {
+    /// `None` if the device is not a partition.
+    async fn new(client: &Client, block_device: &BlockDevice) -> Result<Option<Self>> {
+        if !block_device.is_partition {
+            return Ok(None);
+        }
+        let proxy = client.cached::<PartitionProxy>(&block_device.path).await?;
+        let partition_type = proxy.type_().await?;
+        Ok(Some(Self {
+            type_name: partition_types::name(&partition_type).to_string(),
+            name: proxy.name().await?,
+        }))
+    }
+}
+
+impl DriveInfo {
+    async fn new(client: &Client, block_device: &BlockDevice) -> Result<Option<Self>> {
+        let proxy = client.cached::<BlockProxy>(&block_device.path).await?;
+        let drive = proxy.drive().await?;
+        if drive.len() <= 1 {
+            return Ok(None);
+        }
+        let proxy = client.cached::<DriveProxy>(&drive).await?;
+        let name = format!("{} {}", proxy.vendor().await?, proxy.model().await?)
+            .trim()
+            .to_string();
+        let path = drive.to_string();
+        let name = if name.is_empty() {
+            path.rsplit('/').next().unwrap_or(&path).to_string()
+        } else {
+            name
+        };
+        let bus = proxy.connection_bus().await?;
+        let size = proxy.size().await?;
+        let removable = proxy.removable().await? || proxy.ejectable().await?;
+        Ok(Some(Self {
+            path,
+            name,
+            bus,
+            size,
+            removable,
+        }))
+    }
+}
+
+impl GuiDeviceInfo {
+    pub async fn new(proxy: &BlockProxy<'_>, mount_point: String) -> Result<Self> {
+        let preferred_name = Device::get_preferred_name(proxy).await?;
+        Ok(Self {
+            name: Device::get_name(proxy).await?,
+            path: Device::get_path(proxy).await?,
+            short_name: short_name(&preferred_name),
+            preferred_name,
+            hint_name: proxy.hint_name().await?,
+            label: Device::get_label(proxy).await?,
+            uuid: proxy.id_uuid().await?,
+            fstype: proxy.id_type().await?,
+            size: Device::get_size(proxy).await?,
+            usage: Usage::of(&mount_point),
+            mount_point,
+        })
+    }
+
+    /// The first non-empty of the names in `order`, falling back to the device
+    /// path, shortened unless `long` is set.
+    pub fn display_name(&self, order: &[NameSource], long: bool) -> &str {
+        let device = if long {
+            &self.preferred_name
+        } else {
+            &self.short_name
+        };
+        order
+            .iter()
+            .map(|source| match source {
+                NameSource::HintName => &self.hint_name,
+                NameSource::Label => &self.label,
+                NameSource::Device => device,
+            })
+            .find(|name| !name.is_empty())
+            .unwrap_or(device)
+    }
+
+    /// Whether the filesystem is mounted elsewhere than where udisks2 mounts
+    /// for the current user, e.g. through `/etc/fstab` or by another user, so
+    /// unmounting it likely needs an administrator. udisks2 doesn't expose who
+    /// mounted what, so this goes by the mount point.
+    pub fn mounted_externally(&self) -> bool {
+        if self.mount_point.is_empty() {
+            return false;
+        }
+        let user = env::var("USER").unwrap_or_default();
+        !["/run/media/", "/media/"].iter().any(|dir| {
+            self.mount_point
+                .strip_prefix(dir)
+                .and_then(|rest| rest.split_once('/'))
+                .is_some_and(|(owner, _)| user.is_empty() || owner == user)
+        })
+    }
+
+    /// Sets the mount point, empty if unmounted, updating the usage to match.
+    pub fn set_mount_point(&mut self, mount_point: String) {
+        self.usage = Usage::of(&mount_point);
+        self.mount_point = mount_point;
+    }
+}
+
+/// `sdb1` for `/dev/sdb1`, and `luks-` followed by the first 8 characters of
+/// the UUID for `/dev/mapper/luks-<uuid>`.
+fn short_name(path: &str) -> String {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    match name.strip_prefix("luks-") {
+        Some(uuid) if uuid.chars().count() > 8 => {
+            format!("luks-{}", uuid.chars().take(8).collect::<String>())
+        }
+        _ => name.to_string(),
+    }
+}
+
+impl Usage {
+    /// Queries the usage of the filesystem mounted at `mount_point`, `None`
+    /// if it's empty or the filesystem can't be queried.
+    pub fn of(mount_point: &str) -> Option<Self> {
+        if mount_point.is_empty() {
+            return None;
+        }
+        let stat = rustix::fs::statvfs(mount_point).ok()?;
+        let total = stat.f_blocks * stat.f_frsize;
+        let free = stat.f_bfree * stat.f_frsize;
+        Some(Self {
+            used: total.saturating_sub(free),
+            total,
+        })
+    }
+
+    /// Used fraction of the filesystem, between 0 and 1.
+    pub fn ratio(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.used as f64 / self.total as f64
+        }
+    }
+}
+
+fn no_media() -> color_eyre::Report {
+    eyre!("there is no media in the drive")
+}
+
+fn member() -> color_eyre::Report {
+    eyre!("the device is part of a RAID array or LVM volume group, use that instead")
+}
+
+impl Display for DeviceState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            DeviceState::Locked => "Locked",
+            DeviceState::UnmountedUnlocked => "Unlocked",
+            DeviceState::Mounted => "Mounted",
+            DeviceState::Unmounted => "Unmounted",
+            DeviceState::NoMedia => "No media",
+            DeviceState::Member => "Member",
+            DeviceState::Failed => "Error",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_with_units() {
+        assert_eq!(parse_size("1000000", 0), Ok(1_000_000));
+        assert_eq!(parse_size("512b", 0), Ok(512));
+        assert_eq!(parse_size("500 MB", 0), Ok(500_000_000));
+        assert_eq!(parse_size("32G", 0), Ok(32 << 30));
+        assert_eq!(parse_size("1.5 GiB", 0), Ok(3 << 29));
+        assert_eq!(parse_size("2tb", 0), Ok(2_000_000_000_000));
+        assert_eq!(parse_size(".5k", 0), Ok(512));
+        assert_eq!(parse_size("1.", 0), Ok(1));
+    }
+
+    #[test]
+    fn sizes_keep_integer_precision() {
+        assert_eq!(parse_size("18446744073709551615", 0), Ok(u64::MAX));
+        assert_eq!(parse_size("9007199254740993", 0), Ok(9_007_199_254_740_993));
+        assert_eq!(parse_size("0.1kb", 0), Ok(100));
+    }
+
+    #[test]
+    fn percentages_of_the_whole() {
+        assert_eq!(parse_size("50%", 1000), Ok(500));
+        assert_eq!(parse_size("12.5 %", 1000), Ok(125));
+        assert_eq!(parse_size("150%", 1000), Ok(1500));
+        assert_eq!(parse_size("100%", u64::MAX), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn surrounding_whitespace_is_ignored() {
+        assert_eq!(parse_size("  32 G \t", 0), Ok(32 << 30));
+        assert_eq!(parse_size("\n50%\n", 200), Ok(100));
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        assert!(parse_size("18446744073709551616", 0).is_err());
+        assert!(parse_size("16777216 TiB", 0).is_err());
+        assert!(parse_size("200%", u64::MAX).is_err());
+    }
+
+    #[test]
+    fn rejected_input() {
+        for input in [
+            "",
+            " ",
+            ".",
+            "1.2.3",
+            "-5",
+            "G",
+            "5 parsecs",
+            "5 % G",
+            "1e3",
+        ] {
+            assert!(parse_size(input, 100).is_err(), "{input:?}");
         }
     }
 }
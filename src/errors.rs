@@ -9,7 +9,8 @@ pub fn install_hooks() -> color_eyre::Result<()> {
 
     let panic_hook = panic_hook.into_panic_hook();
     panic::set_hook(Box::new(move |panic_info| {
-        tui::restore().unwrap();
+        // panicking again here would abort before the panic is printed
+        let _ = tui::restore();
         panic_hook(panic_info);
     }));
 
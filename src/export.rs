@@ -0,0 +1,69 @@
+//! Writing the device list as CSV or TSV, e.g. for cataloging the storage of
+//! a machine.
+
+use std::{borrow::Cow, path::Path};
+
+use crate::device::GuiDevice;
+
+const HEADER: [&str; 6] = ["path", "label", "uuid", "fstype", "size", "mount_point"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Tsv,
+}
+
+impl Format {
+    /// CSV for `.csv` files, TSV otherwise, which also pastes into
+    /// spreadsheets as columns.
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("csv") => Format::Csv,
+            _ => Format::Tsv,
+        }
+    }
+
+    fn separator(self) -> &'static str {
+        match self {
+            Format::Csv => ",",
+            Format::Tsv => "\t",
+        }
+    }
+
+    /// Quotes the field as in RFC 4180 for CSV, while TSV can't quote so
+    /// tabs and line breaks become spaces.
+    fn field(self, value: &str) -> Cow<'_, str> {
+        match self {
+            Format::Csv if value.contains([',', '"', '\n', '\r']) => {
+                Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+            }
+            Format::Tsv if value.contains(['\t', '\n', '\r']) => {
+                Cow::Owned(value.replace(['\t', '\n', '\r'], " "))
+            }
+            _ => Cow::Borrowed(value),
+        }
+    }
+}
+
+/// The devices one per line after a header, with sizes in bytes.
+pub fn to_string<'a>(devices: impl IntoIterator<Item = &'a GuiDevice>, format: Format) -> String {
+    let mut out = HEADER.join(format.separator());
+    out.push('\n');
+    for device in devices {
+        let info = &device.info;
+        let path = info.path.to_string_lossy();
+        let size = info.size.to_string();
+        let fields = [
+            &*path,
+            &info.label,
+            &info.uuid,
+            &info.fstype,
+            &size,
+            &info.mount_point,
+        ];
+        let fields: Vec<_> = fields.iter().map(|field| format.field(field)).collect();
+        out.push_str(&fields.join(format.separator()));
+        out.push('\n');
+    }
+    out
+}
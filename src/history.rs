@@ -0,0 +1,106 @@
+//! When each device was last mounted and which devices are hidden, kept
+//! across runs in `$XDG_STATE_HOME/udiskstui/history.toml`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::{eyre::Context, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct History {
+    /// Seconds since the Unix epoch by device key, see [`History::key`].
+    last_mounted: BTreeMap<String, u64>,
+    /// Keys of the devices hidden with x.
+    hidden: BTreeSet<String>,
+}
+
+impl History {
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .wrap_err_with(|| format!("failed to parse {}", path.display())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).wrap_err_with(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = Self::path() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .wrap_err_with(|| format!("failed to create {}", dir.display()))?;
+        }
+        fs::write(&path, toml::to_string(self)?)
+            .wrap_err_with(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+            })
+            .map(|dir| dir.join("udiskstui").join("history.toml"))
+    }
+
+    /// Devices are identified by UUID, which stays the same across reboots,
+    /// or by name for those without one.
+    pub fn key<'a>(uuid: &'a str, name: &'a str) -> &'a str {
+        if uuid.is_empty() {
+            name
+        } else {
+            uuid
+        }
+    }
+
+    pub fn record_mounted(&mut self, key: &str) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.last_mounted.insert(key.to_string(), now);
+    }
+
+    pub fn is_hidden(&self, key: &str) -> bool {
+        self.hidden.contains(key)
+    }
+
+    pub fn set_hidden(&mut self, key: &str, hidden: bool) {
+        if hidden {
+            self.hidden.insert(key.to_string());
+        } else {
+            self.hidden.remove(key);
+        }
+    }
+
+    /// How long ago the device was last mounted.
+    pub fn last_mounted(&self, key: &str) -> Option<Duration> {
+        let secs = *self.last_mounted.get(key)?;
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH + Duration::from_secs(secs))
+            .ok()
+    }
+}
+
+/// Formats `elapsed` as e.g. `5m ago`, using its largest unit.
+pub fn format_ago(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
@@ -0,0 +1,424 @@
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+use serde::Deserialize;
+
+/// What a key does in the devices list, named as in the `[keys]` config
+/// table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    Down,
+    Up,
+    /// Go to the first device, or the nth with a count.
+    First,
+    /// Go to the last device, or the nth with a count.
+    Last,
+    /// Go to the next device in the state given by the next key, e.g. `]m`.
+    Jump,
+    /// Run `enter_action`.
+    Activate,
+    Mount,
+    MountWithOptions,
+    Unmount,
+    Unlock,
+    UndoUnmount,
+    Eject,
+    EjectAll,
+    LockAll,
+    Refresh,
+    Reload,
+    Wipe,
+    PartitionTable,
+    BindMount,
+    MountAt,
+    MountAs,
+    Subvolume,
+    Resize,
+    Check,
+    Repair,
+    TakeOwnership,
+    Label,
+    SizeUnits,
+    DriveSize,
+    LongNames,
+    Sort,
+    MountableOnly,
+    Hide,
+    ShowHidden,
+    Details,
+    DetailsPane,
+    Diagnostics,
+    CopyPath,
+    Export,
+    ShowError,
+    Help,
+}
+
+impl Action {
+    pub const ALL: [Action; 42] = [
+        Action::Quit,
+        Action::Down,
+        Action::Up,
+        Action::First,
+        Action::Last,
+        Action::Jump,
+        Action::Activate,
+        Action::Mount,
+        Action::MountWithOptions,
+        Action::Unmount,
+        Action::Unlock,
+        Action::UndoUnmount,
+        Action::Eject,
+        Action::EjectAll,
+        Action::LockAll,
+        Action::Refresh,
+        Action::Reload,
+        Action::Wipe,
+        Action::PartitionTable,
+        Action::BindMount,
+        Action::MountAt,
+        Action::MountAs,
+        Action::Subvolume,
+        Action::Resize,
+        Action::Check,
+        Action::Repair,
+        Action::TakeOwnership,
+        Action::Label,
+        Action::SizeUnits,
+        Action::DriveSize,
+        Action::LongNames,
+        Action::Sort,
+        Action::MountableOnly,
+        Action::Hide,
+        Action::ShowHidden,
+        Action::Details,
+        Action::DetailsPane,
+        Action::Diagnostics,
+        Action::CopyPath,
+        Action::Export,
+        Action::ShowError,
+        Action::Help,
+    ];
+
+    /// Name in the `[keys]` config table.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Down => "down",
+            Action::Up => "up",
+            Action::First => "first",
+            Action::Last => "last",
+            Action::Jump => "jump",
+            Action::Activate => "activate",
+            Action::Mount => "mount",
+            Action::MountWithOptions => "mount_with_options",
+            Action::Unmount => "unmount",
+            Action::Unlock => "unlock",
+            Action::UndoUnmount => "undo_unmount",
+            Action::Eject => "eject",
+            Action::EjectAll => "eject_all",
+            Action::LockAll => "lock_all",
+            Action::Refresh => "refresh",
+            Action::Reload => "reload",
+            Action::Wipe => "wipe",
+            Action::PartitionTable => "partition_table",
+            Action::BindMount => "bind_mount",
+            Action::MountAt => "mount_at",
+            Action::MountAs => "mount_as",
+            Action::Subvolume => "subvolume",
+            Action::Resize => "resize",
+            Action::Check => "check",
+            Action::Repair => "repair",
+            Action::TakeOwnership => "take_ownership",
+            Action::Label => "label",
+            Action::SizeUnits => "size_units",
+            Action::DriveSize => "drive_size",
+            Action::LongNames => "long_names",
+            Action::Sort => "sort",
+            Action::MountableOnly => "mountable_only",
+            Action::Hide => "hide",
+            Action::ShowHidden => "show_hidden",
+            Action::Details => "details",
+            Action::DetailsPane => "details_pane",
+            Action::Diagnostics => "diagnostics",
+            Action::CopyPath => "copy_path",
+            Action::Export => "export",
+            Action::ShowError => "show_error",
+            Action::Help => "help",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::Down => "Next device",
+            Action::Up => "Previous device",
+            Action::First => "First device, or the nth after a count",
+            Action::Last => "Last device, or the nth after a count",
+            Action::Jump => "Next mounted (m), locked (l) or unlocked (u) device",
+            Action::Activate => "Run enter_action",
+            Action::Mount => "Mount, unlocking first if locked",
+            Action::MountWithOptions => "Mount with options",
+            Action::Unmount => "Unmount and lock",
+            Action::Unlock => "Unlock only",
+            Action::UndoUnmount => "Mount the last unmounted device again",
+            Action::Eject => "Eject",
+            Action::EjectAll => "Eject all removable drives",
+            Action::LockAll => "Unmount and lock every unlocked encrypted device",
+            Action::Refresh => "Refresh",
+            Action::Reload => "Reload the device",
+            Action::Wipe => "Wipe",
+            Action::PartitionTable => "New partition table on the whole disk",
+            Action::BindMount => "Bind mount",
+            Action::MountAt => "Mount at a directory",
+            Action::MountAs => "Mount as a filesystem type",
+            Action::Subvolume => "Mount a btrfs subvolume",
+            Action::Resize => "Resize",
+            Action::Check => "Check the filesystem",
+            Action::Repair => "Repair the filesystem",
+            Action::TakeOwnership => "Make your user own the files on the filesystem",
+            Action::Label => "Set the label",
+            Action::SizeUnits => "Switch size units",
+            Action::DriveSize => "Show the size of the drive next to partitions",
+            Action::LongNames => "Switch between short and full device paths",
+            Action::Sort => "Switch sorting",
+            Action::MountableOnly => "Show only mountable devices",
+            Action::Hide => "Hide the device across runs, or unhide it",
+            Action::ShowHidden => "Show hidden devices",
+            Action::Details => "Details",
+            Action::DetailsPane => "Show the details next to the list",
+            Action::Diagnostics => "Copy diagnostics",
+            Action::CopyPath => "Copy the device path",
+            Action::Export => "Export the device list as CSV or TSV",
+            Action::ShowError => "Show the last error",
+            Action::Help => "Show the keys",
+        }
+    }
+
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            Action::Quit => &["q", "Esc"],
+            Action::Down => &["j", "Down"],
+            Action::Up => &["k", "Up"],
+            Action::First => &["g", "Home"],
+            Action::Last => &["G", "End"],
+            Action::Jump => &["]"],
+            Action::Activate => &["Enter"],
+            Action::Mount => &["m"],
+            Action::MountWithOptions => &["o"],
+            Action::Unmount => &["u"],
+            Action::Unlock => &["U"],
+            Action::UndoUnmount => &["Ctrl-z"],
+            Action::Eject => &["e"],
+            Action::EjectAll => &["X"],
+            Action::LockAll => &["L"],
+            Action::Refresh => &["r"],
+            Action::Reload => &["Ctrl-r"],
+            Action::Wipe => &["W"],
+            Action::PartitionTable => &["P"],
+            Action::BindMount => &["b"],
+            Action::MountAt => &["M"],
+            Action::MountAs => &["T"],
+            Action::Subvolume => &["V"],
+            Action::Resize => &["R"],
+            Action::Check => &["c"],
+            Action::Repair => &["C"],
+            Action::TakeOwnership => &["O"],
+            Action::Label => &["l"],
+            Action::SizeUnits => &["s"],
+            Action::DriveSize => &["z"],
+            Action::LongNames => &["n"],
+            Action::Sort => &["S"],
+            Action::MountableOnly => &["f"],
+            Action::Hide => &["x"],
+            Action::ShowHidden => &["H"],
+            Action::Details => &["i"],
+            Action::DetailsPane => &["I"],
+            Action::Diagnostics => &["D"],
+            Action::CopyPath => &["y"],
+            Action::Export => &["Y"],
+            Action::ShowError => &["E"],
+            Action::Help => &["?"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCode {
+    /// A character, `' '` for Space.
+    Char(char),
+    Enter,
+    Esc,
+    Tab,
+    Backspace,
+    Delete,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    F(u8),
+}
+
+/// A key as written in the config, e.g. `m`, `Space` or `Ctrl-r`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Key {
+    pub code: KeyCode,
+    pub ctrl: bool,
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ctrl, name) = match s.split_once('-') {
+            Some((modifier, name))
+                if !name.is_empty()
+                    && ["ctrl", "c"].contains(&modifier.to_lowercase().as_str()) =>
+            {
+                (true, name)
+            }
+            _ => (false, s),
+        };
+        let mut chars = name.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(if ctrl { c.to_ascii_lowercase() } else { c }),
+            _ => match name.to_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                lower => match lower.strip_prefix('f').map(str::parse) {
+                    Some(Ok(n @ 1..=12)) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key {s:?}")),
+                },
+            },
+        };
+        Ok(Key { code, ctrl })
+    }
+}
+
+impl TryFrom<String> for Key {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::Enter => write!(f, "Enter"),
+            KeyCode::Esc => write!(f, "Esc"),
+            KeyCode::Tab => write!(f, "Tab"),
+            KeyCode::Backspace => write!(f, "Backspace"),
+            KeyCode::Delete => write!(f, "Delete"),
+            KeyCode::Up => write!(f, "Up"),
+            KeyCode::Down => write!(f, "Down"),
+            KeyCode::Left => write!(f, "Left"),
+            KeyCode::Right => write!(f, "Right"),
+            KeyCode::Home => write!(f, "Home"),
+            KeyCode::End => write!(f, "End"),
+            KeyCode::PageUp => write!(f, "PageUp"),
+            KeyCode::PageDown => write!(f, "PageDown"),
+            KeyCode::F(n) => write!(f, "F{n}"),
+        }
+    }
+}
+
+/// The keys bound to each action, the defaults overridden by the `[keys]`
+/// config table. Keys bound in the config are taken away from the actions
+/// they are bound to by default.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(Action, Vec<Key>)>,
+}
+
+impl Keymap {
+    pub fn new(overrides: &BTreeMap<Action, Vec<Key>>) -> Self {
+        let taken: Vec<Key> = overrides.values().flatten().copied().collect();
+        let bindings = Action::ALL
+            .into_iter()
+            .map(|action| {
+                let keys = match overrides.get(&action) {
+                    Some(keys) => keys.clone(),
+                    None => action
+                        .default_keys()
+                        .iter()
+                        .map(|key| key.parse().expect("default keys are valid"))
+                        .filter(|key| !taken.contains(key))
+                        .collect(),
+                };
+                (action, keys)
+            })
+            .collect();
+        Self { bindings }
+    }
+
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|(action, _)| *action)
+    }
+
+    /// The first key bound to `action`, for showing as a hint.
+    pub fn key(&self, action: Action) -> Option<Key> {
+        self.keys(action).first().copied()
+    }
+
+    pub fn keys(&self, action: Action) -> &[Key] {
+        self.bindings
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map_or(&[], |(_, keys)| keys)
+    }
+
+    /// How to run `action` as written in messages, e.g. `press r`.
+    pub fn press(&self, action: Action) -> String {
+        match self.key(action) {
+            Some(key) => format!("press {key}"),
+            None => format!("bind a key to {}", action.name()),
+        }
+    }
+
+    /// The bindings as a `[keys]` config table.
+    pub fn to_toml(&self) -> String {
+        let mut toml = String::from("[keys]\n");
+        for (action, keys) in &self.bindings {
+            let keys: Vec<String> = keys
+                .iter()
+                .map(|key| format!("{:?}", key.to_string()))
+                .collect();
+            toml.push_str(&format!("{} = [{}]\n", action.name(), keys.join(", ")));
+        }
+        toml
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new(&BTreeMap::new())
+    }
+}
@@ -0,0 +1,53 @@
+//! Filesystem label constraints, checked before asking udisks2 to set a label
+//! so the user gets an explanation instead of a generic failure.
+
+/// Characters FAT doesn't allow in volume labels.
+const FAT_FORBIDDEN: &[char] = &[
+    '"', '*', '+', ',', '.', '/', ':', ';', '<', '=', '>', '?', '[', '\\', ']', '|',
+];
+
+/// Adjusts `label` to the conventions of `fstype` and checks that it fits,
+/// returning the label to set or a message explaining why it can't be used.
+pub fn sanitize(fstype: &str, label: &str) -> Result<String, String> {
+    let label = label.trim();
+    match fstype {
+        "vfat" => {
+            let label = label.to_uppercase();
+            if let Some(c) = label.chars().find(|c| FAT_FORBIDDEN.contains(c)) {
+                return Err(format!("FAT labels can't contain {c:?}"));
+            }
+            max_bytes(label, 11, "FAT")
+        }
+        "exfat" => max_utf16(label.to_string(), 15, "exFAT"),
+        "ntfs" => max_utf16(label.to_string(), 128, "NTFS"),
+        "f2fs" => max_utf16(label.to_string(), 512, "F2FS"),
+        "ext2" | "ext3" | "ext4" => max_bytes(label.to_string(), 16, "ext"),
+        "swap" => max_bytes(label.to_string(), 16, "swap"),
+        "xfs" => {
+            if label.contains(' ') {
+                return Err("XFS labels can't contain spaces".to_string());
+            }
+            max_bytes(label.to_string(), 12, "XFS")
+        }
+        "btrfs" => max_bytes(label.to_string(), 255, "Btrfs"),
+        "crypto_LUKS" => Err("unlock the device before changing its label".to_string()),
+        "iso9660" | "" => Err("this device's label can't be changed".to_string()),
+        _ => Ok(label.to_string()),
+    }
+}
+
+fn max_bytes(label: String, max: usize, name: &str) -> Result<String, String> {
+    if label.len() > max {
+        Err(format!("{name} labels max {max} bytes"))
+    } else {
+        Ok(label)
+    }
+}
+
+fn max_utf16(label: String, max: usize, name: &str) -> Result<String, String> {
+    if label.encode_utf16().count() > max {
+        Err(format!("{name} labels max {max} chars"))
+    } else {
+        Ok(label)
+    }
+}
@@ -0,0 +1,32 @@
+//! Listing, mounting, unlocking and ejecting block devices through udisks2.
+//!
+//! The terminal interface lives behind the default `tui` feature, so the
+//! device logic can be used without pulling in `ratatui` and `crossterm`.
+//! The command line parsing, logging and passphrase prompt of the binary
+//! are behind the `cli` feature.
+
+#[cfg(feature = "tui")]
+pub mod app;
+pub mod bind_mount;
+pub mod btrfs;
+#[cfg(feature = "tui")]
+pub mod clipboard;
+pub mod config;
+pub mod device;
+#[cfg(feature = "tui")]
+pub mod errors;
+pub mod export;
+pub mod history;
+pub mod keymap;
+pub mod label;
+pub mod luks;
+pub mod matcher;
+pub mod mount_options;
+pub mod partition_types;
+pub mod processes;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod udisks2;
+
+pub use device::{list_devices, Device, DeviceMessage, DeviceState, GuiDevice, GuiDeviceInfo};
+pub use udisks2::Client;
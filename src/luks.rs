@@ -0,0 +1,172 @@
+//! Reading the tokens enrolled in a LUKS2 header, which udisks2 doesn't
+//! expose, to tell when a volume is meant to be unlocked with a security key
+//! or TPM instead of a passphrase.
+//!
+//! The header is read from the device node itself, e.g. `/dev/sdb1`, which
+//! is usually only readable by root or the `disk` group. For a regular user
+//! the read fails, the tokens are unknown and the passphrase popup doesn't
+//! mention them, so this is a best effort hint rather than a check.
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+use serde::Deserialize;
+
+const MAGIC: &[u8] = b"LUKS\xba\xbe";
+/// The JSON metadata area starts after the 4 KiB binary header.
+const BINARY_HEADER_SIZE: u64 = 4096;
+/// Larger headers are refused rather than read into memory.
+const MAX_HEADER_SIZE: u64 = 4 << 20;
+
+#[derive(Deserialize)]
+struct Metadata {
+    #[serde(default)]
+    tokens: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Types of the tokens enrolled in the LUKS2 header of `device`, e.g.
+/// `systemd-fido2`. Fails if the device can't be read, which usually needs
+/// root, and returns nothing for LUKS1 headers, which have no tokens.
+pub fn token_types(device: &Path) -> io::Result<Vec<String>> {
+    let mut file = File::open(device)?;
+    let mut header = [0; 16];
+    file.read_exact(&mut header)?;
+    if &header[..MAGIC.len()] != MAGIC || u16::from_be_bytes([header[6], header[7]]) != 2 {
+        return Ok(Vec::new());
+    }
+    let header_size = u64::from_be_bytes(header[8..16].try_into().unwrap());
+    if !(BINARY_HEADER_SIZE..=MAX_HEADER_SIZE).contains(&header_size) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid LUKS2 header size",
+        ));
+    }
+
+    let mut json = vec![0; (header_size - BINARY_HEADER_SIZE) as usize];
+    io::copy(
+        &mut file.by_ref().take(BINARY_HEADER_SIZE - header.len() as u64),
+        &mut io::sink(),
+    )?;
+    file.read_exact(&mut json)?;
+    // the area is padded with zeros after the JSON
+    let end = json.iter().position(|&b| b == 0).unwrap_or(json.len());
+    let metadata: Metadata = serde_json::from_slice(&json[..end])?;
+    Ok(metadata
+        .tokens
+        .values()
+        .filter_map(|token| Some(token.get("type")?.as_str()?.to_string()))
+        .collect())
+}
+
+/// What unlocking needs besides a passphrase, if any of the `token_types`
+/// is one udisks2 can't use.
+pub fn describe_tokens(token_types: &[String]) -> Option<&'static str> {
+    token_types.iter().find_map(|token| match token.as_str() {
+        "systemd-fido2" => Some("a FIDO2 security key"),
+        "systemd-tpm2" => Some("the TPM"),
+        "systemd-pkcs11" => Some("a PKCS#11 smart card"),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use super::*;
+
+    /// A header with `version` and `header_size`, followed by `json` padded
+    /// with zeros to the end of the header if the size is a valid one.
+    fn header(version: u16, header_size: u64, json: &str) -> Vec<u8> {
+        let mut header = MAGIC.to_vec();
+        header.extend(version.to_be_bytes());
+        header.extend(header_size.to_be_bytes());
+        header.resize(BINARY_HEADER_SIZE as usize, 0);
+        header.extend(json.as_bytes());
+        if header_size <= MAX_HEADER_SIZE {
+            header.resize(header.len().max(header_size as usize), 0);
+        }
+        header
+    }
+
+    fn write(name: &str, contents: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("udiskstui-luks-{}-{name}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn token_types_of(name: &str, contents: &[u8]) -> io::Result<Vec<String>> {
+        let path = write(name, contents);
+        let result = token_types(&path);
+        fs::remove_file(path).unwrap();
+        result
+    }
+
+    #[test]
+    fn luks2_tokens() {
+        let json = r#"{"keyslots": {}, "tokens": {
+            "0": {"type": "systemd-fido2", "keyslots": ["1"]},
+            "1": {"type": "systemd-tpm2", "keyslots": ["2"]}
+        }}"#;
+        let mut types = token_types_of("tokens", &header(2, 16384, json)).unwrap();
+        types.sort();
+        assert_eq!(types, ["systemd-fido2", "systemd-tpm2"]);
+    }
+
+    #[test]
+    fn luks2_without_tokens() {
+        assert!(
+            token_types_of("empty", &header(2, 16384, r#"{"tokens": {}}"#))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            token_types_of("missing", &header(2, 16384, r#"{"keyslots": {}}"#))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn json_filling_the_whole_area() {
+        let json = r#"{"tokens": {"0": {"type": "systemd-pkcs11"}}}"#;
+        let header = header(2, BINARY_HEADER_SIZE + json.len() as u64, json);
+        assert_eq!(
+            token_types_of("unpadded", &header).unwrap(),
+            ["systemd-pkcs11"]
+        );
+    }
+
+    #[test]
+    fn luks1_and_other_devices_have_no_tokens() {
+        assert!(token_types_of("luks1", &header(1, 0, ""))
+            .unwrap()
+            .is_empty());
+        assert!(token_types_of("zeros", &[0; 4096]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn bad_header_sizes_are_refused() {
+        for (name, size) in [
+            ("small", 1024),
+            ("large", MAX_HEADER_SIZE + 1),
+            ("huge", u64::MAX),
+        ] {
+            let header = header(2, size, r#"{"tokens": {}}"#);
+            let err = token_types_of(name, &header).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{name}");
+        }
+    }
+
+    #[test]
+    fn truncated_and_invalid_headers_fail() {
+        assert!(token_types_of("short", &MAGIC[..4]).is_err());
+        let mut truncated = header(2, 16384, r#"{"tokens": {}}"#);
+        truncated.truncate(8192);
+        assert!(token_types_of("truncated", &truncated).is_err());
+        assert!(token_types_of("invalid", &header(2, 16384, "{not json")).is_err());
+    }
+}
@@ -1,16 +1,100 @@
-use app::App;
+use std::{
+    io::{stdin, IsTerminal},
+    process::ExitCode,
+};
+
+use clap::Parser;
+use cli::Args;
 use color_eyre::Result;
+use udiskstui::{
+    app::App, bind_mount, config::Config, errors, keymap::Keymap, tui, udisks2::UdisksError,
+};
 
-mod app;
-mod device;
-mod errors;
-mod tui;
-mod udisks2;
+mod cli;
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     errors::install_hooks()?;
+    let args = Args::parse();
+    let quiet = args.quiet;
+    let Err(err) = run(args) else {
+        return Ok(ExitCode::SUCCESS);
+    };
+    if quiet {
+        eprintln!("{}: {err:#}", env!("CARGO_PKG_NAME"));
+    } else {
+        // the same as returning the error from main
+        eprintln!("Error: {err:?}");
+    }
+    Ok(ExitCode::from(
+        UdisksError::of(&err).map_or(1, UdisksError::exit_code),
+    ))
+}
+
+fn run(args: Args) -> Result<()> {
+    cli::init_logging(&args)?;
+    if args.no_agent || !stdin().is_terminal() {
+        bind_mount::disable_internal_agent();
+    }
+
+    if let Some(shell) = args.shell_init {
+        cli::shell_init(shell);
+        return Ok(());
+    }
+    let mut config = Config::load()?;
+    if let Some(query) = &args.mount {
+        return cli::mount(query, args.first, &config, args.session_bus);
+    }
+    if let Some(pattern) = &args.mount_all_matching {
+        return cli::mount_all_matching(
+            pattern,
+            args.passphrase_file.as_deref(),
+            &config,
+            args.session_bus,
+        );
+    }
+    if let Some(query) = &args.toggle {
+        return cli::toggle(
+            query,
+            args.first,
+            !args.non_interactive,
+            args.quiet,
+            &config,
+            args.session_bus,
+        );
+    }
+    if args.status {
+        return cli::status(args.device.as_deref(), &config, args.session_bus);
+    }
+    if args.watch {
+        return cli::watch(args.poll_interval, args.quiet, &config, args.session_bus);
+    }
 
-    let mut app = App::new()?;
+    if args.print_keymap {
+        print!("{}", Keymap::new(&config.keys).to_toml());
+        return Ok(());
+    }
+    if args.stay {
+        config.stay = true;
+    }
+    if args.no_color {
+        config.no_color = true;
+    }
+    if args.session_bus {
+        config.session_bus = true;
+    }
+    if args.ascii {
+        config.ascii = Some(true);
+    }
+    if args.compact {
+        config.compact = true;
+    }
+    if args.announce.is_some() {
+        config.announce = args.announce;
+    }
+    if args.idle_timeout.is_some() {
+        config.idle_timeout_secs = args.idle_timeout;
+    }
+    let mut app = App::new(config)?;
     let mut terminal = tui::init()?;
     let result = app.run(&mut terminal);
     tui::restore()?;
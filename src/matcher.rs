@@ -0,0 +1,251 @@
+//! Fuzzy matching of user queries against device names, labels and UUIDs.
+//!
+//! Matching rules:
+//!
+//! - Matching is case-insensitive.
+//! - Every character of the query must appear in the candidate in the same
+//!   order, but not necessarily next to each other, so `bkp` matches
+//!   `Backups 2024`.
+//! - Each matched character scores [`MATCH`], plus [`CONSECUTIVE`] when it
+//!   directly follows the previous match and [`WORD_START`] when it starts a
+//!   word (the first character, or one following a non-alphanumeric one).
+//!   Every skipped character between two matches costs [`GAP`].
+//! - A candidate equal to the query always scores [`EXACT`], which is higher
+//!   than any fuzzy score.
+//! - A device is scored by its best matching field, where the device name is
+//!   tried both as the full path (`/dev/sdb1`) and as its basename (`sdb1`),
+//!   besides the hint name, label and UUID.
+//! - A query with `*` or `?` is a glob instead, matching the whole of any of
+//!   those fields, still case-insensitively, see [`matching_all`].
+
+use crate::device::GuiDeviceInfo;
+
+pub const MATCH: i64 = 16;
+pub const CONSECUTIVE: i64 = 8;
+pub const WORD_START: i64 = 12;
+pub const GAP: i64 = 1;
+pub const EXACT: i64 = i64::MAX;
+
+/// Scores `candidate` against `query`, or returns `None` if it doesn't match.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    let candidate: Vec<char> = candidate.chars().flat_map(char::to_lowercase).collect();
+    if query.is_empty() {
+        return None;
+    }
+    if query == candidate {
+        return Some(EXACT);
+    }
+
+    let mut score = 0;
+    let mut query_chars = query.iter().peekable();
+    let mut last_match: Option<usize> = None;
+    for (i, c) in candidate.iter().enumerate() {
+        let Some(&&q) = query_chars.peek() else {
+            break;
+        };
+        if *c != q {
+            continue;
+        }
+        query_chars.next();
+        score += MATCH;
+        if i == 0 || !candidate[i - 1].is_alphanumeric() {
+            score += WORD_START;
+        }
+        match last_match {
+            Some(last) if last + 1 == i => score += CONSECUTIVE,
+            Some(last) => score -= (i - last - 1) as i64 * GAP,
+            None => {}
+        }
+        last_match = Some(i);
+    }
+
+    if query_chars.peek().is_none() {
+        Some(score)
+    } else {
+        None
+    }
+}
+
+/// Scores a device by its best matching name, hint name, label or UUID.
+pub fn score_device(query: &str, info: &GuiDeviceInfo) -> Option<i64> {
+    let basename = info.name.rsplit('/').next().unwrap_or(&info.name);
+    [
+        info.name.as_str(),
+        basename,
+        &info.hint_name,
+        &info.label,
+        &info.uuid,
+    ]
+    .into_iter()
+    .filter(|field| !field.is_empty())
+    .filter_map(|field| score(query, field))
+    .max()
+}
+
+/// Returns the indices of the devices matching `query`, best match first. If
+/// any device matches exactly, only the exact matches are returned.
+pub fn matching_devices<'a>(
+    query: &str,
+    infos: impl IntoIterator<Item = &'a GuiDeviceInfo>,
+) -> Vec<usize> {
+    let mut matches: Vec<(usize, i64)> = infos
+        .into_iter()
+        .enumerate()
+        .filter_map(|(idx, info)| Some((idx, score_device(query, info)?)))
+        .collect();
+    if matches.iter().any(|(_, score)| *score == EXACT) {
+        matches.retain(|(_, score)| *score == EXACT);
+    }
+    matches.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+    matches.into_iter().map(|(idx, _)| idx).collect()
+}
+
+/// Whether `candidate` matches the glob `pattern` as a whole, where `*`
+/// matches any run of characters and `?` a single one.
+pub fn glob(pattern: &str, candidate: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().flat_map(char::to_lowercase).collect();
+    let candidate: Vec<char> = candidate.chars().flat_map(char::to_lowercase).collect();
+    // where the last `*` was, and the candidate position it matched up to
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut c) = (0, 0);
+    while c < candidate.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, c));
+                p += 1;
+            }
+            Some(&q) if q == '?' || q == candidate[c] => {
+                p += 1;
+                c += 1;
+            }
+            _ => match star {
+                Some((star_p, star_c)) => {
+                    p = star_p + 1;
+                    c = star_c + 1;
+                    star = Some((star_p, star_c + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&q| q == '*')
+}
+
+/// Returns the indices of every device matching `query`: in list order when
+/// it's a glob, e.g. `backup-*`, or like [`matching_devices`] otherwise.
+pub fn matching_all<'a>(
+    query: &str,
+    infos: impl IntoIterator<Item = &'a GuiDeviceInfo>,
+) -> Vec<usize> {
+    if !query.contains(['*', '?']) {
+        return matching_devices(query, infos);
+    }
+    infos
+        .into_iter()
+        .enumerate()
+        .filter(|(_, info)| {
+            let basename = info.name.rsplit('/').next().unwrap_or(&info.name);
+            [
+                info.name.as_str(),
+                basename,
+                &info.hint_name,
+                &info.label,
+                &info.uuid,
+            ]
+            .into_iter()
+            .any(|field| !field.is_empty() && glob(query, field))
+        })
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(name: &str, label: &str) -> GuiDeviceInfo {
+        GuiDeviceInfo {
+            name: name.to_owned(),
+            path: name.into(),
+            preferred_name: name.to_owned(),
+            short_name: name.rsplit('/').next().unwrap_or(name).to_owned(),
+            hint_name: String::new(),
+            label: label.to_owned(),
+            uuid: String::new(),
+            fstype: String::new(),
+            size: 0,
+            mount_point: String::new(),
+            usage: None,
+        }
+    }
+
+    #[test]
+    fn scores_in_order_case_insensitively() {
+        assert_eq!(
+            score("sd", "sdb"),
+            Some(MATCH + WORD_START + MATCH + CONSECUTIVE)
+        );
+        assert_eq!(score("SD", "sdb"), score("sd", "sdb"));
+        assert!(score("bkp", "Backups 2024").is_some());
+        assert_eq!(score("pkb", "Backups 2024"), None);
+        assert_eq!(score("", "sdb"), None);
+        assert_eq!(score("sdb1", "sdb"), None);
+    }
+
+    #[test]
+    fn ranks_consecutive_and_word_starts_higher() {
+        assert!(score("back", "Backups") > score("back", "bxaxcxk"));
+        assert!(score("back", "b-a-c-k") > score("back", "bxaxcxk"));
+        assert!(score("data", "my data") > score("data", "mydata"));
+        assert!(score("abc", "abxc") > score("abc", "abxxxc"));
+        assert_eq!(score("sdb", "SDB"), Some(EXACT));
+    }
+
+    #[test]
+    fn exact_matches_hide_fuzzy_ones() {
+        let infos = [
+            info("/dev/sdb10", ""),
+            info("/dev/sdb1", ""),
+            info("/dev/sdc1", "sdb1"),
+        ];
+        assert_eq!(matching_devices("sdb1", &infos), [1, 2]);
+    }
+
+    #[test]
+    fn best_match_first_and_ties_in_list_order() {
+        let infos = [
+            info("/dev/sda1", "bxaxcxk"),
+            info("/dev/sdb1", "Backups"),
+            info("/dev/sdc1", "Backups"),
+            info("/dev/sdd1", "Music"),
+        ];
+        assert_eq!(matching_devices("back", &infos), [1, 2, 0]);
+    }
+
+    #[test]
+    fn globs_match_whole_fields() {
+        assert!(glob("backup-*", "Backup-2024"));
+        assert!(glob("sd?1", "sdb1"));
+        assert!(glob("*", ""));
+        assert!(glob("a*b*c", "aXbYbZc"));
+        assert!(!glob("sd?1", "sdb10"));
+        assert!(!glob("backup-*", "my backup-2024"));
+    }
+
+    #[test]
+    fn matching_all_globs_in_list_order_or_ranks_fuzzily() {
+        let infos = [
+            info("/dev/sda1", "backup-old"),
+            info("/dev/sdb1", "Music"),
+            info("/dev/sdc1", "backup-2024"),
+        ];
+        assert_eq!(matching_all("backup-*", &infos), [0, 2]);
+        assert_eq!(matching_all("sd?1", &infos), [0, 1, 2]);
+        assert_eq!(matching_all("/dev/sd[", &infos), Vec::<usize>::new());
+        // without `*` or `?` it's fuzzy, so the better match comes first
+        assert_eq!(matching_all("bk24", &infos), [2]);
+        assert_eq!(matching_all("sdc", &infos), [2]);
+        assert_eq!(matching_all("backup", &infos), [0, 2]);
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+};
+
+use color_eyre::{eyre::eyre, Result};
+use zvariant::Value;
+
+/// Options udisks2 allows an unprivileged user to pass for any filesystem.
+const ALLOWED_OPTIONS: &[&str] = &[
+    "exec",
+    "noexec",
+    "nodev",
+    "nosuid",
+    "atime",
+    "noatime",
+    "nodiratime",
+    "relatime",
+    "strictatime",
+    "lazytime",
+    "ro",
+    "rw",
+    "sync",
+    "dirsync",
+    "noload",
+    "acl",
+    "nosymfollow",
+];
+
+/// Additional options udisks2 allows for specific filesystem types. Entries
+/// ending in `=` take a value.
+fn allowed_fstype_options(fstype: &str) -> &'static [&'static str] {
+    match fstype {
+        "vfat" => &[
+            "uid=",
+            "gid=",
+            "flush",
+            "utf8",
+            "shortname=",
+            "umask=",
+            "dmask=",
+            "fmask=",
+            "codepage=",
+            "iocharset=",
+            "usefree",
+            "showexec",
+        ],
+        "ntfs" => &[
+            "uid=",
+            "gid=",
+            "umask=",
+            "dmask=",
+            "fmask=",
+            "locale=",
+            "norecover",
+            "ignore_case",
+            "windows_names",
+            "compression",
+            "nocompression",
+            "big_writes",
+            "nls=",
+            "nohidden",
+            "sys_immutable",
+            "sparse",
+            "showmeta",
+            "prealloc",
+        ],
+        "exfat" => &[
+            "uid=",
+            "gid=",
+            "dmask=",
+            "errors=",
+            "fmask=",
+            "iocharset=",
+            "namecase=",
+            "umask=",
+        ],
+        "iso9660" => &[
+            "uid=",
+            "gid=",
+            "norock",
+            "nojoliet",
+            "iocharset=",
+            "mode=",
+            "dmode=",
+        ],
+        "udf" => &["uid=", "gid=", "iocharset=", "umask="],
+        "btrfs" => &[
+            "compress=",
+            "compress-force=",
+            "datacow",
+            "nodatacow",
+            "datasum",
+            "nodatasum",
+            "autodefrag",
+            "noautodefrag",
+            "degraded",
+            "device=",
+            "discard",
+            "nodiscard",
+            "subvol=",
+            "subvolid=",
+            "space_cache",
+        ],
+        _ => &[],
+    }
+}
+
+/// The types [`allowed_fstype_options`] knows of.
+const KNOWN_FSTYPES: &[&str] = &["vfat", "ntfs", "exfat", "iso9660", "udf", "btrfs"];
+
+/// Filesystem types without file owners of their own, which take the owner
+/// of every file as mount options.
+const OWNERLESS_FSTYPES: &[&str] = &["vfat", "exfat", "ntfs"];
+
+#[derive(Debug, Clone, Default)]
+pub struct MountOptions {
+    /// Comma separated mount options, as accepted by `mount -o`.
+    pub options: Option<String>,
+    /// Filesystem type to mount as instead of the detected one, e.g. `ntfs3`.
+    pub fstype: Option<String>,
+    /// Who to own the files as, for the filesystems without owners.
+    pub owner: Option<Owner>,
+    /// Options added by filesystem type, where `{uid}`, `{gid}` and `{user}`
+    /// are replaced with the current user's, e.g. `vfat = "flush"`.
+    pub profiles: BTreeMap<String, String>,
+}
+
+/// The `uid`, `gid` and `umask` options.
+#[derive(Debug, Clone)]
+pub struct Owner {
+    pub uid: u32,
+    pub gid: u32,
+    /// Octal, e.g. `022`.
+    pub umask: Option<String>,
+}
+
+impl Owner {
+    pub fn current(umask: Option<String>) -> Self {
+        Self {
+            uid: rustix::process::getuid().as_raw(),
+            gid: rustix::process::getgid().as_raw(),
+            umask,
+        }
+    }
+}
+
+impl MountOptions {
+    pub fn with_options(options: impl Into<String>) -> Self {
+        Self {
+            options: Some(options.into()),
+            ..Default::default()
+        }
+    }
+
+    /// These options with the profile for `fstype`, or the explicit
+    /// filesystem type if set, added, and the [`Owner`] ones when it has no
+    /// owners. The owner options are left out for the other types, which
+    /// reject them, and any option already given isn't added again.
+    pub fn for_fstype(&self, fstype: &str) -> Self {
+        let mut options = self.clone();
+        let owner = options.owner.take();
+        let fstype = self.fstype.as_deref().unwrap_or(fstype);
+        let mut added: Vec<String> = self
+            .profiles
+            .get(fstype)
+            .map(|profile| expand(profile))
+            .iter()
+            .flat_map(|profile| profile.split(','))
+            .map(str::trim)
+            .filter(|option| !option.is_empty())
+            .map(String::from)
+            .collect();
+        if let Some(owner) = owner.filter(|_| OWNERLESS_FSTYPES.contains(&fstype)) {
+            added.extend(
+                [
+                    ("uid", Some(owner.uid.to_string())),
+                    ("gid", Some(owner.gid.to_string())),
+                    ("umask", owner.umask),
+                ]
+                .into_iter()
+                .filter_map(|(name, value)| Some(format!("{name}={}", value?))),
+            );
+        }
+        let mut names: Vec<String> = self
+            .options
+            .iter()
+            .flat_map(|options| options.split(','))
+            .map(|option| option_name(option).to_string())
+            .collect();
+        added.retain(|option| {
+            let name = option_name(option);
+            if names.iter().any(|given| given == name) {
+                return false;
+            }
+            names.push(name.to_string());
+            true
+        });
+        if added.is_empty() {
+            return options;
+        }
+        options.options = Some(match &self.options {
+            Some(given) if !given.trim().is_empty() => format!("{given},{}", added.join(",")),
+            _ => added.join(","),
+        });
+        options
+    }
+
+    /// Fails listing the options udisks2 would reject for `fstype`, or for
+    /// the explicit filesystem type if set.
+    pub fn validate(&self, fstype: &str) -> Result<()> {
+        let Some(options) = &self.options else {
+            return Ok(());
+        };
+        let fstype = self.fstype.as_deref().unwrap_or(fstype);
+        let fstype_options = allowed_fstype_options(fstype);
+        let rejected: Vec<&str> = options
+            .split(',')
+            .map(str::trim)
+            .filter(|option| !option.is_empty())
+            .filter(|option| {
+                let name = match option.split_once('=') {
+                    Some((name, _)) => name,
+                    None => option,
+                };
+                let allowed = |allowed: &&str| match allowed.strip_suffix('=') {
+                    Some(allowed) => option.contains('=') && allowed == name,
+                    None => *allowed == *option,
+                };
+                !ALLOWED_OPTIONS.iter().any(allowed) && !fstype_options.iter().any(allowed)
+            })
+            .collect();
+        if rejected.is_empty() {
+            Ok(())
+        } else {
+            Err(eyre!(
+                "mount options not permitted by udisks2 for {}: {}",
+                if fstype.is_empty() { "unknown" } else { fstype },
+                rejected.join(",")
+            ))
+        }
+    }
+
+    /// Like [`MountOptions::validate`] before the filesystem type is known,
+    /// failing only on the options udisks2 rejects for every type.
+    pub fn validate_any(&self) -> Result<()> {
+        if let Some(fstype) = &self.fstype {
+            return self.validate(fstype);
+        }
+        let Some(options) = &self.options else {
+            return Ok(());
+        };
+        let rejected: Vec<&str> = options
+            .split(',')
+            .map(str::trim)
+            .filter(|option| !option.is_empty())
+            .filter(|option| {
+                !ALLOWED_OPTIONS.contains(option)
+                    && !KNOWN_FSTYPES.iter().any(|fstype| {
+                        allowed_fstype_options(fstype).iter().any(|allowed| {
+                            match allowed.strip_suffix('=') {
+                                Some(allowed) => option_name(option) == allowed,
+                                None => allowed == option,
+                            }
+                        })
+                    })
+            })
+            .collect();
+        if rejected.is_empty() {
+            Ok(())
+        } else {
+            Err(eyre!(
+                "mount options not permitted by udisks2: {}",
+                rejected.join(",")
+            ))
+        }
+    }
+
+    pub fn to_dbus(&self) -> HashMap<&str, Value<'_>> {
+        let mut options = HashMap::new();
+        if let Some(mount_options) = &self.options {
+            options.insert("options", mount_options.as_str().into());
+        }
+        if let Some(fstype) = &self.fstype {
+            options.insert("fstype", fstype.as_str().into());
+        }
+        options
+    }
+}
+
+/// The name of a `name=value` option, or the whole option without a value.
+fn option_name(option: &str) -> &str {
+    option
+        .split_once('=')
+        .map_or(option, |(name, _)| name)
+        .trim()
+}
+
+/// Replaces `{uid}`, `{gid}` and `{user}` with those of the current user.
+fn expand(profile: &str) -> String {
+    profile
+        .replace("{uid}", &rustix::process::getuid().as_raw().to_string())
+        .replace("{gid}", &rustix::process::getgid().as_raw().to_string())
+        .replace("{user}", &env::var("USER").unwrap_or_default())
+}
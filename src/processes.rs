@@ -0,0 +1,47 @@
+//! Finding the processes keeping a filesystem busy, by scanning `/proc`.
+
+use std::{fs, path::Path};
+
+#[derive(Debug)]
+pub struct Process {
+    pub pid: u32,
+    pub name: String,
+}
+
+/// Processes with their working directory, root, executable or an open file
+/// at or below `mount_point`. Processes that can't be inspected, e.g. those
+/// of other users, are skipped.
+pub fn using(mount_point: &Path) -> Vec<Process> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let mut processes: Vec<Process> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse().ok()?;
+            let dir = entry.path();
+            let links = ["cwd", "root", "exe"]
+                .into_iter()
+                .map(|link| dir.join(link));
+            let fds = fs::read_dir(dir.join("fd"))
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|fd| fd.path());
+            let busy = links
+                .chain(fds)
+                .filter_map(|link| fs::read_link(link).ok())
+                .any(|target| target.starts_with(mount_point));
+            if !busy {
+                return None;
+            }
+            let name = fs::read_to_string(dir.join("comm")).unwrap_or_default();
+            Some(Process {
+                pid,
+                name: name.trim_end().to_string(),
+            })
+        })
+        .collect();
+    processes.sort_by_key(|process| process.pid);
+    processes
+}
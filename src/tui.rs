@@ -1,18 +1,69 @@
-use std::io::{self, stderr, Stderr};
+use std::{
+    io::{self, stderr, Stderr},
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-use crossterm::{execute, terminal::*};
+use crossterm::{
+    cursor::Show,
+    event::{DisableBracketedPaste, EnableBracketedPaste},
+    execute,
+    terminal::*,
+};
 use ratatui::prelude::*;
 
+use crate::device::DeviceState;
+
 pub type Tui = Terminal<CrosstermBackend<Stderr>>;
 
+/// Whether the terminal is set up for the TUI and needs restoring.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
 pub fn init() -> io::Result<Tui> {
-    execute!(stderr(), EnterAlternateScreen)?;
+    ACTIVE.store(true, Ordering::SeqCst);
+    execute!(stderr(), EnterAlternateScreen, EnableBracketedPaste)?;
     enable_raw_mode()?;
     Terminal::new(CrosstermBackend::new(stderr()))
 }
 
+/// Undoes [`init`], doing nothing if the terminal was never set up or was
+/// already restored, so it's safe to call from the panic hook.
 pub fn restore() -> io::Result<()> {
-    execute!(stderr(), LeaveAlternateScreen)?;
+    if !ACTIVE.swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
     disable_raw_mode()?;
+    execute!(stderr(), DisableBracketedPaste, LeaveAlternateScreen, Show)?;
     Ok(())
 }
+
+impl DeviceState {
+    pub fn style(&self) -> Style {
+        match self {
+            DeviceState::Mounted => Style::new().green(),
+            DeviceState::UnmountedUnlocked => Style::new().yellow(),
+            DeviceState::Locked => Style::new().red(),
+            DeviceState::Unmounted => Style::new().gray(),
+            DeviceState::NoMedia | DeviceState::Member => Style::new().dark_gray(),
+            DeviceState::Failed => Style::new().magenta(),
+        }
+    }
+
+    pub fn glyph(&self, ascii: bool) -> &'static str {
+        match (self, ascii) {
+            (DeviceState::Mounted, false) => "●",
+            (DeviceState::UnmountedUnlocked, false) => "◐",
+            (DeviceState::Locked, false) => "■",
+            (DeviceState::Unmounted, false) => "○",
+            (DeviceState::NoMedia, false) => "◌",
+            (DeviceState::Member, false) => "◇",
+            (DeviceState::Failed, false) => "✗",
+            (DeviceState::Mounted, true) => "*",
+            (DeviceState::UnmountedUnlocked, true) => "+",
+            (DeviceState::Locked, true) => "#",
+            (DeviceState::Unmounted, true) => "-",
+            (DeviceState::NoMedia, true) => ".",
+            (DeviceState::Member, true) => "=",
+            (DeviceState::Failed, true) => "!",
+        }
+    }
+}
@@ -1,49 +1,349 @@
-use std::io::Cursor;
+use std::{
+    collections::HashMap,
+    env,
+    fmt::Display,
+    future::Future,
+    io::Cursor,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
 
-use color_eyre::Result;
+use color_eyre::{Report, Result};
+use log::{debug, info, warn};
+use serde::Deserialize;
 
-use zbus::{proxy, Connection};
+use zbus::{fdo, names::BusName, proxy, Connection};
 use zbus_xml::Node;
 use zvariant::{ObjectPath, OwnedObjectPath};
 
+const RECONNECT_ATTEMPTS: u32 = 5;
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_millis(250);
+const RETRY_INITIAL_DELAY: Duration = Duration::from_millis(200);
+
+const SERVICE: &str = "org.freedesktop.UDisks2";
+
+/// Address of the bus to connect to instead of the system or session bus,
+/// e.g. of a mock udisks2 in CI. `--session-bus` takes precedence.
+pub const BUS_ADDRESS_VAR: &str = "UDISKSTUI_BUS_ADDRESS";
+/// Name of the service to talk to instead of `org.freedesktop.UDisks2`.
+pub const SERVICE_VAR: &str = "UDISKSTUI_SERVICE";
+
 #[derive(Debug, Clone)]
 pub struct Client {
-    connection: Connection,
+    connection: Arc<RwLock<Connection>>,
+    reconnecting: Arc<AtomicBool>,
+    /// Bus connected to, and reconnected to when the connection is lost.
+    bus: Bus,
+    service: BusName<'static>,
+    /// Proxies built so far by interface and path. They keep their cached
+    /// properties up to date by listening to `PropertiesChanged`, so they can
+    /// be reused instead of fetching every property again.
+    proxies: Arc<Mutex<HashMap<(&'static str, OwnedObjectPath), zbus::Proxy<'static>>>>,
+}
+
+#[derive(Debug, Clone)]
+enum Bus {
+    System,
+    Session,
+    Address(String),
 }
 
 impl Client {
+    /// Connects to udisks2 on the system bus, or on the session bus if it
+    /// isn't on the system bus but is there, as in some containers. The bus
+    /// address and service name can be overridden with [`BUS_ADDRESS_VAR`]
+    /// and [`SERVICE_VAR`].
     pub async fn new() -> zbus::Result<Self> {
-        let connection = zbus::Connection::system().await?;
-        Ok(Client { connection })
+        let service = service()?;
+        if let Some(address) = env_var(BUS_ADDRESS_VAR) {
+            info!("connecting to the bus at {address} from ${BUS_ADDRESS_VAR}");
+            return Self::connect(Bus::Address(address), service).await;
+        }
+        let system = match Self::connect(Bus::System, service.clone()).await {
+            Ok(client) if client.has_service().await => return Ok(client),
+            result => result,
+        };
+        match Self::connect(Bus::Session, service).await {
+            Ok(client) if client.has_service().await => {
+                info!("udisks2 is not on the system bus, using the session bus");
+                Ok(client)
+            }
+            // report the system bus errors, which is where udisks2 should be
+            _ => system,
+        }
+    }
+
+    /// Connects to udisks2 on the session bus only, ignoring
+    /// [`BUS_ADDRESS_VAR`].
+    pub async fn session() -> zbus::Result<Self> {
+        Self::connect(Bus::Session, service()?).await
+    }
+
+    /// [`Client::session`] if `session_bus`, [`Client::new`] otherwise.
+    pub async fn connect_to(session_bus: bool) -> zbus::Result<Self> {
+        if session_bus {
+            Self::session().await
+        } else {
+            Self::new().await
+        }
+    }
+
+    /// Connects to `service` on the bus at `address`, e.g. a mock udisks2 on
+    /// a private bus for testing.
+    pub async fn with_address(address: &str, service: &str) -> zbus::Result<Self> {
+        Self::connect(
+            Bus::Address(address.to_string()),
+            BusName::try_from(service)?.into_owned(),
+        )
+        .await
+    }
+
+    async fn connect(bus: Bus, service: BusName<'static>) -> zbus::Result<Self> {
+        let connection = Self::open(&bus).await?;
+        Ok(Client {
+            connection: Arc::new(RwLock::new(connection)),
+            reconnecting: Arc::new(AtomicBool::new(false)),
+            bus,
+            service,
+            proxies: Arc::default(),
+        })
+    }
+
+    async fn open(bus: &Bus) -> zbus::Result<Connection> {
+        match bus {
+            Bus::System => Connection::system().await,
+            Bus::Session => Connection::session().await,
+            Bus::Address(address) => {
+                zbus::connection::Builder::address(address.as_str())?
+                    .build()
+                    .await
+            }
+        }
+    }
+
+    /// Whether the service is running or can be started on the bus.
+    async fn has_service(&self) -> bool {
+        let Ok(proxy) = fdo::DBusProxy::new(&self.conn()).await else {
+            return false;
+        };
+        if proxy
+            .name_has_owner(self.service.as_ref())
+            .await
+            .unwrap_or(false)
+        {
+            return true;
+        }
+        proxy
+            .list_activatable_names()
+            .await
+            .is_ok_and(|names| names.iter().any(|name| **name == self.service))
+    }
+
+    pub fn conn(&self) -> Connection {
+        self.connection.read().unwrap().clone()
+    }
+
+    /// Starts building a proxy to the udisks2 service this client talks to.
+    pub fn proxy<'a, P>(&self) -> zbus::proxy::Builder<'a, P>
+    where
+        P: zbus::proxy::ProxyDefault,
+    {
+        zbus::proxy::Builder::new(&self.conn())
+            .destination(self.service.clone())
+            .expect("the service name is validated when connecting")
+    }
+
+    pub fn is_reconnecting(&self) -> bool {
+        self.reconnecting.load(Ordering::Relaxed)
+    }
+
+    /// Returns the proxy for `path`, building it only the first time.
+    pub async fn cached<P>(&self, path: &ObjectPath<'_>) -> zbus::Result<P>
+    where
+        P: zbus::proxy::ProxyDefault + zbus::proxy::ProxyImpl<'static> + From<zbus::Proxy<'static>>,
+    {
+        let key = (P::INTERFACE.unwrap_or_default(), path.to_owned().into());
+        if let Some(proxy) = self.proxies.lock().unwrap().get(&key) {
+            return Ok(P::from(proxy.clone()));
+        }
+        let proxy: P = self.proxy::<P>().path(path.to_owned())?.build().await?;
+        self.proxies
+            .lock()
+            .unwrap()
+            .insert(key, proxy.inner().clone());
+        Ok(proxy)
+    }
+
+    /// Drops the cached proxies for `path`, so their properties are fetched
+    /// again the next time they are needed.
+    pub fn forget(&self, path: &ObjectPath<'_>) {
+        self.proxies
+            .lock()
+            .unwrap()
+            .retain(|(_, cached), _| cached.as_ref() != *path);
+    }
+
+    /// Drops the cached proxies of block devices not in `present`, and of the
+    /// drives no remaining block device is on, so the proxies of removed
+    /// devices don't pile up.
+    fn evict_missing(&self, present: &[OwnedObjectPath]) {
+        let block_interface =
+            <BlockProxy as zbus::proxy::ProxyDefault>::INTERFACE.unwrap_or_default();
+        let drive_interface =
+            <DriveProxy as zbus::proxy::ProxyDefault>::INTERFACE.unwrap_or_default();
+        fn paths_with(
+            proxies: &HashMap<(&'static str, OwnedObjectPath), zbus::Proxy<'static>>,
+            wanted: &str,
+        ) -> Vec<OwnedObjectPath> {
+            proxies
+                .keys()
+                .filter(|(interface, _)| *interface == wanted)
+                .map(|(_, path)| path.clone())
+                .collect()
+        }
+
+        let mut proxies = self.proxies.lock().unwrap();
+        let gone_devices: Vec<_> = paths_with(&proxies, block_interface)
+            .into_iter()
+            .filter(|path| !present.contains(path))
+            .collect();
+        proxies.retain(|(_, path), _| !gone_devices.contains(path));
+
+        let used_drives: Vec<OwnedObjectPath> = proxies
+            .iter()
+            .filter(|((interface, _), _)| *interface == block_interface)
+            .filter_map(|(_, proxy)| {
+                BlockProxy::from(proxy.clone())
+                    .cached_drive()
+                    .ok()
+                    .flatten()
+            })
+            .collect();
+        let gone_drives: Vec<_> = paths_with(&proxies, drive_interface)
+            .into_iter()
+            .filter(|path| !used_drives.contains(path))
+            .collect();
+        proxies.retain(|(_, path), _| !gone_drives.contains(path));
+    }
+
+    /// Replaces the connection shared by every clone of this client with a
+    /// new one, retrying with exponential backoff.
+    pub async fn reconnect(&self) -> zbus::Result<()> {
+        warn!("connection to D-Bus lost, reconnecting");
+        self.reconnecting.store(true, Ordering::Relaxed);
+        let mut delay = RECONNECT_INITIAL_DELAY;
+        let mut result = Ok(());
+        for _ in 0..RECONNECT_ATTEMPTS {
+            match Self::open(&self.bus).await {
+                Ok(connection) => {
+                    *self.connection.write().unwrap() = connection;
+                    // they belong to the old connection
+                    self.proxies.lock().unwrap().clear();
+                    result = Ok(());
+                    break;
+                }
+                Err(err) => {
+                    warn!("failed to reconnect to D-Bus: {err}");
+                    result = Err(err);
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+            }
+        }
+        self.reconnecting.store(false, Ordering::Relaxed);
+        result
+    }
+
+    /// Runs `f`, and if it fails because the D-Bus connection was lost,
+    /// reconnects and runs it once more.
+    pub async fn retry_on_disconnect<T, F, Fut>(&self, f: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        match f().await {
+            Err(err) if is_disconnected(&err) => {
+                self.reconnect().await?;
+                f().await
+            }
+            result => result,
+        }
+    }
+
+    /// Awaits `call` once, for calls that mustn't be repeated like a format or
+    /// a resize, as udisks2 may have started carrying them out before the
+    /// connection was lost. If it was lost, this reconnects for the next
+    /// calls and fails with a hint to check the device before trying again.
+    pub async fn reconnect_on_disconnect<T>(
+        &self,
+        call: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        match call.await {
+            Err(err) if is_disconnected(&err) => {
+                self.reconnect().await?;
+                Err(err.wrap_err(
+                    "lost the connection to udisks2, refresh and check the device before trying again",
+                ))
+            }
+            result => result,
+        }
+    }
+
+    /// Runs `f` like [`Client::retry_on_disconnect`], retrying it up to
+    /// `retries` times with exponential backoff while it fails with a
+    /// [transient](UdisksError::is_transient) error.
+    pub async fn retry_transient<T, F, Fut>(&self, retries: u32, f: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut delay = RETRY_INITIAL_DELAY;
+        for _ in 0..retries {
+            match self.retry_on_disconnect(&f).await {
+                Err(err) if UdisksError::of(&err).is_some_and(UdisksError::is_transient) => {
+                    warn!("retrying in {delay:?} after a transient error: {err:#}");
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+        self.retry_on_disconnect(f).await
     }
 
-    pub fn conn(&self) -> &Connection {
-        &self.connection
+    /// Signals udisks2 emits when objects are added, removed or change, for
+    /// noticing hotplugs and mounts done by other programs.
+    pub async fn changes(&self) -> Result<zbus::MessageStream> {
+        let rule = zbus::MatchRule::builder()
+            .msg_type(zbus::message::Type::Signal)
+            .sender(self.service.clone())?
+            .path_namespace("/org/freedesktop/UDisks2")?
+            .build();
+        Ok(zbus::MessageStream::for_match_rule(rule, &self.conn(), None).await?)
     }
 
     pub async fn get_block_devices(&self) -> Result<Vec<BlockDevice>> {
-        let manager_proxy = ManagerProxy::new(&self.connection).await?;
+        let manager_proxy = self.proxy::<ManagerProxy>().build().await?;
+        debug!("Manager.GetBlockDevices");
         let resp = manager_proxy.get_block_devices(Default::default()).await?;
+        self.evict_missing(&resp);
         let mut devices = Vec::new();
         for path in resp {
-            let kind = match self.block_device_kind(&path).await? {
-                Some(kind) => kind,
-                None => continue,
-            };
-            devices.push(BlockDevice { path, kind });
+            if let Some(device) = self.block_device(path).await? {
+                devices.push(device);
+            }
         }
 
         Ok(devices)
     }
 
-    async fn block_device_kind(
-        &self,
-        object_path: &ObjectPath<'_>,
-    ) -> Result<Option<BlockDeviceKind>> {
-        let proxy = BlockProxy::builder(&self.connection)
-            .path(object_path)?
-            .build()
-            .await?;
+    /// The block device at `path`, or `None` if it isn't of a kind that is
+    /// listed.
+    async fn block_device(&self, path: OwnedObjectPath) -> Result<Option<BlockDevice>> {
+        let proxy = self.cached::<BlockProxy>(&path).await?;
         if proxy.hint_ignore().await? {
             return Ok(None);
         }
@@ -51,13 +351,27 @@ impl Client {
             return Ok(None);
         }
 
-        let xml_descriptor = proxy.inner().introspect().await?;
-        let r = Cursor::new(xml_descriptor);
-        let node = Node::from_reader(r)?;
-        let interfaces = node.interfaces();
+        let interfaces = self.interfaces(&path).await?;
+        let Some(kind) = self.block_device_kind(&proxy, &interfaces).await? else {
+            return Ok(None);
+        };
+        let is_partition = interfaces
+            .iter()
+            .any(|interface| interface == "org.freedesktop.UDisks2.Partition");
+        Ok(Some(BlockDevice {
+            path,
+            kind,
+            is_partition,
+        }))
+    }
 
+    async fn block_device_kind(
+        &self,
+        proxy: &BlockProxy<'_>,
+        interfaces: &[String],
+    ) -> Result<Option<BlockDeviceKind>> {
         for interface in interfaces {
-            match interface.name().as_str() {
+            match interface.as_str() {
                 "org.freedesktop.UDisks2.Filesystem" => {
                     return Ok(Some(BlockDeviceKind::Filesystem));
                 }
@@ -67,20 +381,212 @@ impl Client {
                 _ => {}
             }
         }
+
+        if proxy.id_usage().await? == "raid" {
+            return Ok(Some(BlockDeviceKind::Member));
+        }
+
+        let drive = proxy.drive().await?;
+        if drive.len() > 1 {
+            let drive_proxy = self.cached::<DriveProxy>(&drive).await?;
+            if drive_proxy.media_removable().await? && !drive_proxy.media_available().await? {
+                return Ok(Some(BlockDeviceKind::NoMedia));
+            }
+        }
         Ok(None)
     }
+
+    /// Whether the object at `object_path` holds a filesystem, which the
+    /// cleartext device of an encrypted one doesn't when it holds LVM or
+    /// partitions, whose filesystems are listed on their own.
+    pub async fn has_filesystem(&self, object_path: &ObjectPath<'_>) -> Result<bool> {
+        Ok(self
+            .interfaces(object_path)
+            .await?
+            .iter()
+            .any(|interface| interface == "org.freedesktop.UDisks2.Filesystem"))
+    }
+
+    pub async fn interfaces(&self, object_path: &ObjectPath<'_>) -> Result<Vec<String>> {
+        let proxy = self
+            .proxy::<fdo::IntrospectableProxy>()
+            .path(object_path)?
+            .build()
+            .await?;
+        let xml_descriptor = proxy.introspect().await?;
+        let r = Cursor::new(xml_descriptor);
+        let node = Node::from_reader(r)?;
+        Ok(node
+            .interfaces()
+            .iter()
+            .map(|interface| interface.name().to_string())
+            .collect())
+    }
+}
+
+/// The value of the environment variable `name`, unless unset or empty.
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// [`SERVICE`], or the one set with [`SERVICE_VAR`].
+fn service() -> zbus::Result<BusName<'static>> {
+    match env_var(SERVICE_VAR) {
+        Some(service) => Ok(BusName::try_from(service)?),
+        None => Ok(BusName::from_static_str(SERVICE)?),
+    }
+}
+
+/// Whether the connection to udisks2 was lost, anywhere in the chain of
+/// `err`. A reply timeout doesn't count, as the call may still be running.
+fn is_disconnected(err: &Report) -> bool {
+    err.chain()
+        .filter_map(|err| err.downcast_ref::<zbus::Error>())
+        .any(|err| match err {
+            zbus::Error::InputOutput(_) => true,
+            zbus::Error::MethodError(name, _, _) => matches!(
+                name.as_str(),
+                "org.freedesktop.DBus.Error.Disconnected"
+                    | "org.freedesktop.DBus.Error.ServiceUnknown"
+            ),
+            zbus::Error::FDO(err) => matches!(
+                **err,
+                fdo::Error::Disconnected(_)
+                    | fdo::Error::ServiceUnknown(_)
+                    | fdo::Error::IOError(_)
+            ),
+            _ => false,
+        })
+}
+
+/// The kinds of errors that are handled differently, from the name of the
+/// D-Bus error udisks2 replied with. Anything else stays a plain
+/// [`Report`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UdisksError {
+    /// Authentication failed or was dismissed.
+    NotAuthorized,
+    /// Not allowed at all, e.g. a mount option or a device mounted by
+    /// another user.
+    PermissionDenied,
+    /// The device is in use, e.g. by a program with a file open on it.
+    Busy,
+    /// The device is gone, usually because it was unplugged.
+    NoSuchDevice,
+    /// The operation was cancelled.
+    Cancelled,
+    /// Usually goes away by itself, e.g. right after a device is plugged in
+    /// while udisks2 is still probing it.
+    Timeout,
+    /// Another unmount of the device is still running.
+    AlreadyUnmounting,
+    /// The operation isn't supported, e.g. for the filesystem of the device.
+    NotSupported,
+}
+
+impl UdisksError {
+    pub fn from_dbus_name(name: &str) -> Option<Self> {
+        let name = name
+            .strip_prefix("org.freedesktop.UDisks2.Error.")
+            .or_else(|| name.strip_prefix("org.freedesktop.DBus.Error."))?;
+        Some(match name {
+            "NotAuthorized" | "NotAuthorizedCanObtain" | "NotAuthorizedDismissed" => {
+                Self::NotAuthorized
+            }
+            "AccessDenied" | "OptionNotPermitted" | "MountedByOtherUser" => Self::PermissionDenied,
+            "DeviceBusy" => Self::Busy,
+            "UnknownObject" | "UnknownInterface" => Self::NoSuchDevice,
+            "Cancelled" | "AlreadyCancelled" => Self::Cancelled,
+            "Timedout" | "Timeout" | "TimedOut" => Self::Timeout,
+            "AlreadyUnmounting" => Self::AlreadyUnmounting,
+            "NotSupported" | "NotSupportedError" => Self::NotSupported,
+            _ => return None,
+        })
+    }
+
+    /// The kind of `err`, from a `UdisksError` or a D-Bus error anywhere in
+    /// its chain.
+    pub fn of(err: &Report) -> Option<Self> {
+        err.chain()
+            .find_map(|err| err.downcast_ref::<UdisksError>().copied())
+            .or_else(|| Self::from_dbus_name(&dbus_error_name(err)?))
+    }
+
+    /// Whether trying again later may succeed without doing anything else.
+    pub fn is_transient(self) -> bool {
+        matches!(self, Self::Timeout | Self::AlreadyUnmounting)
+    }
+
+    /// What to do about it, if anything.
+    pub fn hint(self) -> Option<&'static str> {
+        match self {
+            Self::NotAuthorized => Some("authenticate when asked, or run a polkit agent"),
+            Self::Busy => Some("close the programs using the device and try again"),
+            Self::NoSuchDevice => Some("the device was probably removed, refresh the list"),
+            Self::Timeout | Self::AlreadyUnmounting => Some("try again in a moment"),
+            Self::PermissionDenied | Self::Cancelled | Self::NotSupported => None,
+        }
+    }
+
+    /// Exit status of the commands that don't start the TUI, following
+    /// `sysexits.h`.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::NotAuthorized | Self::PermissionDenied => 77,
+            Self::Busy | Self::Timeout | Self::AlreadyUnmounting => 75,
+            Self::NoSuchDevice | Self::NotSupported => 69,
+            Self::Cancelled => 1,
+        }
+    }
+}
+
+impl Display for UdisksError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::NotAuthorized => "not authorized",
+            Self::PermissionDenied => "permission denied",
+            Self::Busy => "device is busy",
+            Self::NoSuchDevice => "no such device",
+            Self::Cancelled => "cancelled",
+            Self::Timeout => "timed out",
+            Self::AlreadyUnmounting => "already being unmounted",
+            Self::NotSupported => "not supported",
+        })
+    }
+}
+
+impl std::error::Error for UdisksError {}
+
+/// Name of the D-Bus error behind `err`, e.g.
+/// `org.freedesktop.UDisks2.Error.DeviceBusy`.
+pub fn dbus_error_name(err: &Report) -> Option<String> {
+    err.chain()
+        .find_map(|err| match err.downcast_ref::<zbus::Error>()? {
+            zbus::Error::MethodError(name, _, _) => Some(name.to_string()),
+            zbus::Error::FDO(err) => Some(zbus::DBusError::name(err.as_ref()).to_string()),
+            _ => None,
+        })
 }
 
 #[derive(Debug, Clone)]
 pub struct BlockDevice {
     pub path: OwnedObjectPath,
     pub kind: BlockDeviceKind,
+    /// Whether it has the `Partition` interface.
+    pub is_partition: bool,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum BlockDeviceKind {
     Filesystem,
     Encrypted,
+    /// A drive with removable media but none inserted, e.g. an empty card
+    /// reader.
+    NoMedia,
+    /// A RAID member or LVM physical volume, which can only be mounted
+    /// through the array or volume group it's part of.
+    Member,
 }
 
 #[proxy(
@@ -103,20 +609,75 @@ trait Block {
     #[zbus(property)]
     fn hint_ignore(&self) -> zbus::Result<bool>;
 
+    #[zbus(property)]
+    fn hint_name(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn hint_auto(&self) -> zbus::Result<bool>;
+
     #[zbus(property)]
     fn drive(&self) -> zbus::Result<OwnedObjectPath>;
 
     #[zbus(property)]
     fn device(&self) -> zbus::Result<Vec<u8>>;
 
+    #[zbus(property)]
+    fn preferred_device(&self) -> zbus::Result<Vec<u8>>;
+
     #[zbus(property)]
     fn id_label(&self) -> zbus::Result<String>;
 
+    #[zbus(property)]
+    fn id_type(&self) -> zbus::Result<String>;
+
+    /// What the contents are used for, e.g. `filesystem`, or `raid` for RAID
+    /// members and LVM physical volumes.
+    #[zbus(property)]
+    fn id_usage(&self) -> zbus::Result<String>;
+
+    /// The array the device is a member of, `/` if none.
+    #[zbus(property, name = "MDRaidMember")]
+    fn mdraid_member(&self) -> zbus::Result<OwnedObjectPath>;
+
+    #[zbus(property, name = "IdUUID")]
+    fn id_uuid(&self) -> zbus::Result<String>;
+
     #[zbus(property)]
     fn size(&self) -> zbus::Result<u64>;
 
+    #[zbus(property)]
+    fn read_only(&self) -> zbus::Result<bool>;
+
     #[zbus(property)]
     fn crypto_backing_device(&self) -> zbus::Result<OwnedObjectPath>;
+
+    fn format(
+        &self,
+        type_: &str,
+        options: std::collections::HashMap<&str, zvariant::Value<'_>>,
+    ) -> zbus::Result<()>;
+}
+
+#[proxy(
+    default_service = "org.freedesktop.UDisks2",
+    interface = "org.freedesktop.UDisks2.Partition"
+)]
+trait Partition {
+    fn resize(
+        &self,
+        size: u64,
+        options: std::collections::HashMap<&str, zvariant::Value<'_>>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn type_(&self) -> zbus::Result<String>;
+
+    /// The block device of the whole disk the partition is on.
+    #[zbus(property)]
+    fn table(&self) -> zbus::Result<OwnedObjectPath>;
+
+    #[zbus(property)]
+    fn name(&self) -> zbus::Result<String>;
 }
 
 #[proxy(
@@ -134,6 +695,39 @@ trait Filesystem {
         options: std::collections::HashMap<&str, zvariant::Value<'_>>,
     ) -> zbus::Result<()>;
 
+    fn set_label(
+        &self,
+        label: &str,
+        options: std::collections::HashMap<&str, zvariant::Value<'_>>,
+    ) -> zbus::Result<()>;
+
+    /// Returns whether the filesystem is consistent.
+    fn check(
+        &self,
+        options: std::collections::HashMap<&str, zvariant::Value<'_>>,
+    ) -> zbus::Result<bool>;
+
+    /// Returns whether the filesystem could be repaired.
+    fn repair(
+        &self,
+        options: std::collections::HashMap<&str, zvariant::Value<'_>>,
+    ) -> zbus::Result<bool>;
+
+    /// Resizes the filesystem to `size` bytes, or to fill its block device if
+    /// it's 0.
+    fn resize(
+        &self,
+        size: u64,
+        options: std::collections::HashMap<&str, zvariant::Value<'_>>,
+    ) -> zbus::Result<()>;
+
+    /// Makes the calling user own the root of the filesystem, or every file
+    /// on it with the `recursive` option.
+    fn take_ownership(
+        &self,
+        options: std::collections::HashMap<&str, zvariant::Value<'_>>,
+    ) -> zbus::Result<()>;
+
     #[zbus(property)]
     fn mount_points(&self) -> zbus::Result<Vec<Vec<u8>>>;
 }
@@ -154,6 +748,14 @@ trait Encrypted {
         options: std::collections::HashMap<&str, zvariant::Value<'_>>,
     ) -> zbus::Result<OwnedObjectPath>;
 
+    /// Resizes the encrypted volume to `size` bytes, or to fill its block
+    /// device if it's 0.
+    fn resize(
+        &self,
+        size: u64,
+        options: std::collections::HashMap<&str, zvariant::Value<'_>>,
+    ) -> zbus::Result<()>;
+
     #[zbus(property)]
     fn cleartext_device(&self) -> zbus::Result<OwnedObjectPath>;
 }
@@ -163,8 +765,159 @@ trait Encrypted {
     default_service = "org.freedesktop.UDisks2"
 )]
 trait Drive {
+    #[zbus(property)]
+    fn vendor(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn model(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn removable(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn ejectable(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn connection_bus(&self) -> zbus::Result<String>;
+
+    /// Size in bytes, 0 without media.
+    #[zbus(property)]
+    fn size(&self) -> zbus::Result<u64>;
+
+    #[zbus(property)]
+    fn media_removable(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn media_available(&self) -> zbus::Result<bool>;
+
     fn eject(
         &self,
         options: std::collections::HashMap<&str, &zbus::zvariant::Value<'_>>,
     ) -> zbus::Result<()>;
 }
+
+#[proxy(
+    interface = "org.freedesktop.UDisks2.Drive.Ata",
+    default_service = "org.freedesktop.UDisks2"
+)]
+trait DriveAta {
+    #[zbus(property)]
+    fn security_erase_unit_minutes(&self) -> zbus::Result<i32>;
+
+    #[zbus(property)]
+    fn security_enhanced_erase_unit_minutes(&self) -> zbus::Result<i32>;
+
+    #[zbus(property)]
+    fn security_frozen(&self) -> zbus::Result<bool>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.UDisks2.MDRaid",
+    default_service = "org.freedesktop.UDisks2"
+)]
+trait MDRaid {
+    #[zbus(property)]
+    fn name(&self) -> zbus::Result<String>;
+}
+
+/// Only present when udisks2's LVM module is loaded.
+#[proxy(
+    interface = "org.freedesktop.UDisks2.PhysicalVolume",
+    default_service = "org.freedesktop.UDisks2"
+)]
+trait PhysicalVolume {
+    #[zbus(property)]
+    fn volume_group(&self) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.UDisks2.VolumeGroup",
+    default_service = "org.freedesktop.UDisks2"
+)]
+trait VolumeGroup {
+    #[zbus(property)]
+    fn name(&self) -> zbus::Result<String>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_from_udisks2_and_dbus_names() {
+        let cases = [
+            (
+                "org.freedesktop.UDisks2.Error.NotAuthorizedCanObtain",
+                UdisksError::NotAuthorized,
+            ),
+            (
+                "org.freedesktop.UDisks2.Error.OptionNotPermitted",
+                UdisksError::PermissionDenied,
+            ),
+            (
+                "org.freedesktop.DBus.Error.AccessDenied",
+                UdisksError::PermissionDenied,
+            ),
+            (
+                "org.freedesktop.UDisks2.Error.DeviceBusy",
+                UdisksError::Busy,
+            ),
+            (
+                "org.freedesktop.DBus.Error.UnknownObject",
+                UdisksError::NoSuchDevice,
+            ),
+            (
+                "org.freedesktop.UDisks2.Error.Cancelled",
+                UdisksError::Cancelled,
+            ),
+            (
+                "org.freedesktop.UDisks2.Error.Timedout",
+                UdisksError::Timeout,
+            ),
+            ("org.freedesktop.DBus.Error.TimedOut", UdisksError::Timeout),
+            (
+                "org.freedesktop.UDisks2.Error.AlreadyUnmounting",
+                UdisksError::AlreadyUnmounting,
+            ),
+            (
+                "org.freedesktop.UDisks2.Error.NotSupported",
+                UdisksError::NotSupported,
+            ),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(UdisksError::from_dbus_name(name), Some(expected), "{name}");
+        }
+    }
+
+    #[test]
+    fn unknown_names_are_none() {
+        assert_eq!(
+            UdisksError::from_dbus_name("org.freedesktop.UDisks2.Error.Failed"),
+            None
+        );
+        assert_eq!(
+            UdisksError::from_dbus_name("org.example.Error.DeviceBusy"),
+            None
+        );
+        assert_eq!(UdisksError::from_dbus_name("DeviceBusy"), None);
+    }
+
+    #[test]
+    fn exit_codes_follow_sysexits() {
+        assert_eq!(UdisksError::NotAuthorized.exit_code(), 77);
+        assert_eq!(UdisksError::PermissionDenied.exit_code(), 77);
+        assert_eq!(UdisksError::Busy.exit_code(), 75);
+        assert_eq!(UdisksError::Timeout.exit_code(), 75);
+        assert_eq!(UdisksError::AlreadyUnmounting.exit_code(), 75);
+        assert_eq!(UdisksError::NoSuchDevice.exit_code(), 69);
+        assert_eq!(UdisksError::NotSupported.exit_code(), 69);
+        assert_eq!(UdisksError::Cancelled.exit_code(), 1);
+    }
+
+    #[test]
+    fn kind_is_found_in_the_chain() {
+        let err = Report::new(UdisksError::Busy).wrap_err("failed to unmount /dev/sdb1");
+        assert_eq!(UdisksError::of(&err), Some(UdisksError::Busy));
+        assert_eq!(UdisksError::of(&Report::msg("something else")), None);
+    }
+}
@@ -0,0 +1,422 @@
+//! Runs the device logic against a mock udisks2 on a private bus, started
+//! with `dbus-daemon`. The tests are skipped when it isn't installed.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+};
+
+use secstr::SecStr;
+use udiskstui::{
+    device::DeviceState,
+    mount_options::MountOptions,
+    udisks2::{BlockDeviceKind, UdisksError},
+    Client, Device, DeviceMessage,
+};
+use zbus::{fdo, interface, object_server::SignalContext, ObjectServer};
+use zvariant::{OwnedObjectPath, Value};
+
+const SERVICE: &str = "org.freedesktop.UDisks2";
+const PLAIN: &str = "/org/freedesktop/UDisks2/block_devices/mock0";
+const ENCRYPTED: &str = "/org/freedesktop/UDisks2/block_devices/mock1";
+const CLEARTEXT: &str = "/org/freedesktop/UDisks2/block_devices/dm_2d0";
+const PASSPHRASE: &str = "hunter2";
+
+/// A `dbus-daemon` for the tests alone, killed when dropped.
+struct Bus {
+    daemon: Child,
+    address: String,
+}
+
+impl Bus {
+    fn start() -> Option<Self> {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address=1"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| eprintln!("skipping, can't start dbus-daemon: {err}"))
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take()?)
+            .read_line(&mut address)
+            .ok()?;
+        Some(Self {
+            daemon,
+            address: address.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+struct Manager {
+    devices: Arc<Mutex<Vec<OwnedObjectPath>>>,
+}
+
+#[interface(name = "org.freedesktop.UDisks2.Manager")]
+impl Manager {
+    fn get_block_devices(&self, _options: HashMap<String, Value<'_>>) -> Vec<OwnedObjectPath> {
+        self.devices.lock().unwrap().clone()
+    }
+}
+
+struct Block {
+    device: &'static str,
+    id_type: &'static str,
+    crypto_backing_device: &'static str,
+}
+
+impl Block {
+    fn new(device: &'static str, id_type: &'static str) -> Self {
+        Self {
+            device,
+            id_type,
+            crypto_backing_device: "/",
+        }
+    }
+}
+
+#[interface(name = "org.freedesktop.UDisks2.Block")]
+impl Block {
+    #[zbus(property)]
+    fn hint_ignore(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn hint_name(&self) -> String {
+        String::new()
+    }
+
+    #[zbus(property)]
+    fn hint_auto(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn drive(&self) -> OwnedObjectPath {
+        OwnedObjectPath::try_from("/").unwrap()
+    }
+
+    #[zbus(property)]
+    fn device(&self) -> Vec<u8> {
+        let mut bytes = self.device.as_bytes().to_vec();
+        bytes.push(0);
+        bytes
+    }
+
+    #[zbus(property)]
+    fn preferred_device(&self) -> Vec<u8> {
+        self.device()
+    }
+
+    #[zbus(property, name = "IdLabel")]
+    fn id_label(&self) -> String {
+        "DATA".to_string()
+    }
+
+    #[zbus(property, name = "IdType")]
+    fn id_type(&self) -> String {
+        self.id_type.to_string()
+    }
+
+    #[zbus(property, name = "IdUUID")]
+    fn id_uuid(&self) -> String {
+        format!("uuid-{}", self.device.rsplit('/').next().unwrap())
+    }
+
+    #[zbus(property)]
+    fn size(&self) -> u64 {
+        1 << 30
+    }
+
+    #[zbus(property)]
+    fn read_only(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn crypto_backing_device(&self) -> OwnedObjectPath {
+        OwnedObjectPath::try_from(self.crypto_backing_device).unwrap()
+    }
+}
+
+struct Filesystem {
+    mount_point: Option<String>,
+}
+
+#[interface(name = "org.freedesktop.UDisks2.Filesystem")]
+impl Filesystem {
+    async fn mount(
+        &mut self,
+        _options: HashMap<String, Value<'_>>,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<String> {
+        if self.mount_point.is_some() {
+            return Err(fdo::Error::Failed("already mounted".to_string()));
+        }
+        let mount_point = format!("/media/mock/{}", ctxt.path().rsplit('/').next().unwrap());
+        self.mount_point = Some(mount_point.clone());
+        self.mount_points_changed(&ctxt).await?;
+        Ok(mount_point)
+    }
+
+    async fn unmount(
+        &mut self,
+        _options: HashMap<String, Value<'_>>,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<()> {
+        if self.mount_point.take().is_none() {
+            return Err(fdo::Error::Failed("not mounted".to_string()));
+        }
+        self.mount_points_changed(&ctxt).await?;
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn mount_points(&self) -> Vec<Vec<u8>> {
+        self.mount_point
+            .iter()
+            .map(|mount_point| {
+                let mut bytes = mount_point.as_bytes().to_vec();
+                bytes.push(0);
+                bytes
+            })
+            .collect()
+    }
+}
+
+struct Encrypted {
+    unlocked: bool,
+    devices: Arc<Mutex<Vec<OwnedObjectPath>>>,
+}
+
+#[interface(name = "org.freedesktop.UDisks2.Encrypted")]
+impl Encrypted {
+    async fn unlock(
+        &mut self,
+        passphrase: &str,
+        _options: HashMap<String, Value<'_>>,
+        #[zbus(object_server)] server: &ObjectServer,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<OwnedObjectPath> {
+        if passphrase != PASSPHRASE {
+            return Err(fdo::Error::AccessDenied("wrong passphrase".to_string()));
+        }
+        let mut block = Block::new("/dev/dm-0", "ext4");
+        block.crypto_backing_device = ENCRYPTED;
+        server.at(CLEARTEXT, block).await?;
+        server
+            .at(CLEARTEXT, Filesystem { mount_point: None })
+            .await?;
+        let cleartext = OwnedObjectPath::try_from(CLEARTEXT).unwrap();
+        self.devices.lock().unwrap().push(cleartext.clone());
+        self.unlocked = true;
+        self.cleartext_device_changed(&ctxt).await?;
+        Ok(cleartext)
+    }
+
+    async fn lock(
+        &mut self,
+        _options: HashMap<String, Value<'_>>,
+        #[zbus(object_server)] server: &ObjectServer,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<()> {
+        server.remove::<Filesystem, _>(CLEARTEXT).await?;
+        server.remove::<Block, _>(CLEARTEXT).await?;
+        self.devices
+            .lock()
+            .unwrap()
+            .retain(|path| path.as_str() != CLEARTEXT);
+        self.unlocked = false;
+        self.cleartext_device_changed(&ctxt).await?;
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn cleartext_device(&self) -> OwnedObjectPath {
+        OwnedObjectPath::try_from(if self.unlocked { CLEARTEXT } else { "/" }).unwrap()
+    }
+}
+
+/// Serves a mock udisks2 with an unmounted ext4 filesystem and a locked
+/// LUKS device holding another one, returning the connection keeping it up.
+async fn serve(address: &str) -> zbus::Connection {
+    let devices = Arc::new(Mutex::new(vec![
+        OwnedObjectPath::try_from(PLAIN).unwrap(),
+        OwnedObjectPath::try_from(ENCRYPTED).unwrap(),
+    ]));
+    zbus::connection::Builder::address(address)
+        .unwrap()
+        .name(SERVICE)
+        .unwrap()
+        .serve_at(
+            "/org/freedesktop/UDisks2/Manager",
+            Manager {
+                devices: Arc::clone(&devices),
+            },
+        )
+        .unwrap()
+        .serve_at(PLAIN, Block::new("/dev/mock0", "ext4"))
+        .unwrap()
+        .serve_at(PLAIN, Filesystem { mount_point: None })
+        .unwrap()
+        .serve_at(ENCRYPTED, Block::new("/dev/mock1", "crypto_LUKS"))
+        .unwrap()
+        .serve_at(
+            ENCRYPTED,
+            Encrypted {
+                unlocked: false,
+                devices,
+            },
+        )
+        .unwrap()
+        .build()
+        .await
+        .unwrap()
+}
+
+/// Lists the devices of the mock, which is kept up by the returned
+/// connection.
+async fn devices(bus: &Bus) -> (zbus::Connection, Client, Vec<Device>) {
+    let server = serve(&bus.address).await;
+    let client = Client::with_address(&bus.address, SERVICE).await.unwrap();
+    let devices = udiskstui::list_devices(&client).await.unwrap();
+    (server, client, devices)
+}
+
+/// State of `device`, fetching the properties again instead of relying on
+/// the cached ones having been updated by the signals yet.
+async fn state(client: &Client, device: &Device) -> DeviceState {
+    device.forget_cached().await.unwrap();
+    Device::get_state(client, device.block_device())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn lists_block_devices() {
+    let Some(bus) = Bus::start() else { return };
+    let (_server, client, devices) = devices(&bus).await;
+
+    let block_devices = client.get_block_devices().await.unwrap();
+    let kinds: Vec<_> = block_devices
+        .iter()
+        .map(|device| (device.path.as_str(), device.kind))
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            (PLAIN, BlockDeviceKind::Filesystem),
+            (ENCRYPTED, BlockDeviceKind::Encrypted),
+        ]
+    );
+    assert_eq!(state(&client, &devices[0]).await, DeviceState::Unmounted);
+    assert_eq!(state(&client, &devices[1]).await, DeviceState::Locked);
+}
+
+#[tokio::test]
+async fn mounts_and_unmounts() {
+    let Some(bus) = Bus::start() else { return };
+    let (_server, client, devices) = devices(&bus).await;
+    let device = &devices[0];
+
+    let msg = device.mount(0, None, &MountOptions::default()).await;
+    assert!(
+        matches!(&msg, Ok(DeviceMessage::Mounted(0, mount_point)) if mount_point == "/media/mock/mock0"),
+        "{msg:?}"
+    );
+    assert_eq!(state(&client, device).await, DeviceState::Mounted);
+
+    let msg = device.mount(0, None, &MountOptions::default()).await;
+    assert!(
+        matches!(msg, Ok(DeviceMessage::AlreadyMounted(0, _))),
+        "{msg:?}"
+    );
+
+    let msg = device.unmount(0).await;
+    assert!(matches!(msg, Ok(DeviceMessage::Unmounted(0))), "{msg:?}");
+    assert_eq!(state(&client, device).await, DeviceState::Unmounted);
+}
+
+#[tokio::test]
+async fn unlocks_mounts_and_locks() {
+    let Some(bus) = Bus::start() else { return };
+    let (_server, client, devices) = devices(&bus).await;
+    let device = &devices[1];
+    let options = MountOptions::default();
+
+    let msg = device.mount(1, None, &options).await;
+    assert!(
+        matches!(msg, Ok(DeviceMessage::PassphraseRequired(1, Some(_), _))),
+        "{msg:?}"
+    );
+
+    let wrong = SecStr::from("wrong");
+    let err = device.mount(1, Some(wrong), &options).await.unwrap_err();
+    assert_eq!(UdisksError::of(&err), Some(UdisksError::PermissionDenied));
+    assert_eq!(state(&client, device).await, DeviceState::Locked);
+
+    let msg = device
+        .mount(1, Some(SecStr::from(PASSPHRASE)), &options)
+        .await;
+    assert!(
+        matches!(&msg, Ok(DeviceMessage::UnlockedAndMounted(1, mount_point, info))
+            if mount_point == "/media/mock/dm_2d0" && info.name == "/dev/dm-0"),
+        "{msg:?}"
+    );
+    assert_eq!(state(&client, device).await, DeviceState::Mounted);
+    // the cleartext device is shown as part of the encrypted one
+    assert_eq!(client.get_block_devices().await.unwrap().len(), 2);
+
+    let msg = device.unmount(1).await;
+    assert!(
+        matches!(msg, Ok(DeviceMessage::UnmountedAndLocked(1, _))),
+        "{msg:?}"
+    );
+    assert_eq!(state(&client, device).await, DeviceState::Locked);
+}
+
+#[tokio::test]
+async fn unlocks_without_mounting() {
+    let Some(bus) = Bus::start() else { return };
+    let (_server, client, devices) = devices(&bus).await;
+    let device = &devices[1];
+
+    let msg = device.unlock(1, None).await;
+    assert!(
+        matches!(msg, Ok(DeviceMessage::PassphraseRequired(1, None, _))),
+        "{msg:?}"
+    );
+
+    let msg = device.unlock(1, Some(SecStr::from(PASSPHRASE))).await;
+    assert!(
+        matches!(&msg, Ok(DeviceMessage::Unlocked(1, info)) if info.name == "/dev/dm-0"),
+        "{msg:?}"
+    );
+    assert_eq!(state(&client, device).await, DeviceState::UnmountedUnlocked);
+
+    let msg = device.unmount(1).await;
+    assert!(matches!(msg, Ok(DeviceMessage::Locked(1))), "{msg:?}");
+    assert_eq!(state(&client, device).await, DeviceState::Locked);
+}
+
+#[tokio::test]
+async fn connects_to_the_bus_from_the_environment() {
+    let Some(bus) = Bus::start() else { return };
+    let _server = serve(&bus.address).await;
+    // no other test reads these
+    std::env::set_var(udiskstui::udisks2::BUS_ADDRESS_VAR, &bus.address);
+    std::env::set_var(udiskstui::udisks2::SERVICE_VAR, SERVICE);
+
+    let client = Client::new().await.unwrap();
+    assert_eq!(client.get_block_devices().await.unwrap().len(), 2);
+}